@@ -0,0 +1,303 @@
+use std::{f32::consts::PI, time::Duration};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{keystate::KeyState, ship::{Ship, HIT_AREA_RADIUS}, world::World};
+
+// Bullets passing closer than this are dodged
+const DANGER_RADIUS: f32 = HIT_AREA_RADIUS * 2.5;
+// How far ahead bullets are tracked
+const DODGE_HORIZON: Duration = Duration::from_millis(600);
+// Bots close in on targets farther than this, in px
+const KEEP_DISTANCE: f32 = 180.0;
+// Bots only fire at targets closer than this, in px
+const FIRE_RANGE: f32 = 350.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    // How often the bot changes its keys
+    fn reaction_time(&self) -> Duration {
+        match self {
+            Difficulty::Easy => Duration::from_millis(300),
+            Difficulty::Normal => Duration::from_millis(150),
+            Difficulty::Hard => Duration::from_millis(50),
+        }
+    }
+
+    // The largest error of its aim, in rad
+    fn aim_error(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.35,
+            Difficulty::Normal => 0.12,
+            Difficulty::Hard => 0.0,
+        }
+    }
+
+    // How far off the aim may be when it fires, in rad
+    fn fire_tolerance(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.3,
+            Difficulty::Normal => 0.15,
+            Difficulty::Hard => 0.08,
+        }
+    }
+
+    fn dodges(&self) -> bool {
+        *self != Difficulty::Easy
+    }
+}
+
+/// A computer player, which plays through the same keys as humans do.
+///
+/// A bot aims at the nearest ship, leading it by the bullet speed, and
+/// dodges bullets coming at it from the normal difficulty up.
+#[derive(Debug)]
+pub struct Bot {
+    difficulty: Difficulty,
+    rng: ChaCha8Rng,
+    keys: KeyState,
+    next_think_tick: u64,
+}
+
+impl Bot {
+    pub fn new(difficulty: Difficulty, seed: u64) -> Self {
+        Self {
+            difficulty,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            keys: KeyState::new(),
+            next_think_tick: 0,
+        }
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    /// Keys of the bot playing the ship of player `id` for the next tick.
+    pub fn think(&mut self, id: usize, world: &World) -> KeyState {
+        let clock = world.clock();
+        if clock.tick < self.next_think_tick {
+            return self.keys;
+        }
+        self.next_think_tick = clock.tick + clock.ticks(self.difficulty.reaction_time()).max(1);
+
+        self.keys = if world.is_playing() {
+            self.decide(id, world)
+        } else {
+            // Gets ready at once
+            KeyState { space: true, ..KeyState::new() }
+        };
+
+        self.keys
+    }
+
+    fn decide(&mut self, id: usize, world: &World) -> KeyState {
+        let mut keys = KeyState::new();
+        let Some(ship) = world.ship(id).filter(|ship| ship.is_alive()) else {
+            return keys;
+        };
+        let pos = ship.pos();
+        let rad = ship.rad();
+        let bounds = world.bounds();
+
+        if self.difficulty.dodges() {
+            if let Some(escape) = escape_angle(ship, world) {
+                steer(&mut keys, angle_diff(escape, rad));
+                keys.up = true;
+                return keys;
+            }
+        }
+
+        let target = world.ships()
+            .map(|(_, other)| other)
+            .filter(|other| other.player_num != ship.player_num && other.is_alive())
+            .min_by(|a, b| {
+                bounds.wrapped_dist2(pos, a.pos()).total_cmp(&bounds.wrapped_dist2(pos, b.pos()))
+            });
+        let Some(target) = target else {
+            return keys;
+        };
+
+        let bullet_speed = world.settings().bullet_speed * world.clock().dt();
+        let velocity = (target.speed().x, target.speed().y);
+        let error = self.difficulty.aim_error();
+        let error = if error > 0.0 { self.rng.random_range(-error..=error) } else { 0.0 };
+
+        // Flies across the edges if the target is closer that way
+        let to_target = bounds.displacement(pos, target.pos());
+        let (dx, dy) = lead((to_target.x, to_target.y), velocity, bullet_speed);
+        let diff = angle_diff(dy.atan2(dx) + error, rad);
+        steer(&mut keys, diff);
+        keys.up = (dx * dx + dy * dy).sqrt() > KEEP_DISTANCE && diff.abs() < PI / 4.0;
+
+        // but only fires straight at it, as bullets die at the edges
+        let straight = target.pos() - pos;
+        let (sx, sy) = lead((straight.x, straight.y), velocity, bullet_speed);
+        let fire_diff = angle_diff(sy.atan2(sx) + error, rad);
+        keys.space = fire_diff.abs() < self.difficulty.fire_tolerance()
+            && (sx * sx + sy * sy).sqrt() < FIRE_RANGE;
+
+        keys
+    }
+}
+
+/// The heading to flee in if a bullet of another ship is about to hit `ship`.
+/// Bullets die at the edges, so only those coming straight at it count.
+fn escape_angle(ship: &Ship, world: &World) -> Option<f32> {
+    let pos = ship.pos();
+    let horizon = world.clock().ticks(DODGE_HORIZON) as f32;
+
+    world.bullets().iter()
+        .filter(|bullet| bullet.owner != ship.player_num)
+        .filter_map(|bullet| {
+            let d = pos - bullet.pos;
+            let (dx, dy) = (d.x, d.y);
+            let (vx, vy) = (bullet.speed().x, bullet.speed().y);
+            let v2 = vx * vx + vy * vy;
+            if v2 == 0.0 {
+                return None;
+            }
+
+            // Ticks until the bullet is closest to the ship
+            let t = ((dx * vx + dy * vy) / v2).clamp(0.0, horizon);
+            let (cx, cy) = (dx - vx * t, dy - vy * t);
+            let miss2 = cx * cx + cy * cy;
+
+            (miss2 < DANGER_RADIUS * DANGER_RADIUS).then_some((t, vx, vy, cx, cy))
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, vx, vy, cx, cy)| {
+            // Moves off the line of the bullet on the side the ship is on
+            let side = if vx * cy - vy * cx >= 0.0 { PI / 2.0 } else { -PI / 2.0 };
+            vy.atan2(vx) + side
+        })
+}
+
+/// Where to aim to hit a target at `(dx, dy)` moving at `(vx, vy)` px/tick
+/// with a bullet of `speed` px/tick. Falls back to the target itself if no
+/// bullet can catch it.
+fn lead((dx, dy): (f32, f32), (vx, vy): (f32, f32), speed: f32) -> (f32, f32) {
+    // |d + v t| = speed * t
+    let a = vx * vx + vy * vy - speed * speed;
+    let b = 2.0 * (dx * vx + dy * vy);
+    let c = dx * dx + dy * dy;
+
+    let t = if a.abs() < f32::EPSILON {
+        (b < 0.0).then(|| -c / b)
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        (discriminant >= 0.0).then(|| {
+            let root = discriminant.sqrt();
+            [(-b - root) / (2.0 * a), (-b + root) / (2.0 * a)].into_iter()
+                .filter(|t| *t > 0.0)
+                .fold(f32::INFINITY, f32::min)
+        }).filter(|t| t.is_finite())
+    };
+
+    match t {
+        Some(t) => (dx + vx * t, dy + vy * t),
+        None => (dx, dy),
+    }
+}
+
+/// `to - from`, wrapped into `(-PI, PI]`.
+fn angle_diff(to: f32, from: f32) -> f32 {
+    let diff = (to - from).rem_euclid(PI * 2.0);
+    if diff > PI { diff - PI * 2.0 } else { diff }
+}
+
+fn steer(keys: &mut KeyState, diff: f32) {
+    // Ships turn clockwise with the right key, as angles grow
+    keys.right = diff > 0.0;
+    keys.left = diff < 0.0;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use vector::Position;
+
+    use crate::{settings::Settings, world::Event};
+
+    use super::*;
+
+    #[test]
+    fn lead_test() {
+        let (target, velocity, speed) = ((100.0, 0.0), (0.0, 3.0), 7.0);
+        let (ax, ay) = lead(target, velocity, speed);
+
+        // The bullet and the target arrive at the aimed point together
+        let t = (ax * ax + ay * ay).sqrt() / speed;
+        assert!((ax - target.0).abs() < 1e-3);
+        assert!((ay - (target.1 + velocity.1 * t)).abs() < 1e-3);
+
+        assert_eq!(lead(target, (20.0, 0.0), speed), target);
+    }
+
+    #[test]
+    fn angle_diff_test() {
+        assert!((angle_diff(0.1, PI * 2.0 - 0.1) - 0.2).abs() < 1e-5);
+        assert!((angle_diff(-PI / 2.0, PI / 2.0).abs() - PI).abs() < 1e-5);
+    }
+
+    #[test]
+    fn fire_across_edge_test() {
+        let mut world = World::new(3, 60, Settings::default());
+        world.add_player(1);
+        world.add_player(2);
+        let space = KeyState { space: true, ..KeyState::new() };
+        while !world.is_playing() {
+            world.step(&HashMap::from([(1, space), (2, space)]));
+        }
+
+        // The target is closer across the right edge, which no bullet passes
+        world.place(1, Position::new(340.0, 300.0), 0.0);
+        world.place(2, Position::new(10.0, 300.0), 0.0);
+        let keys = Bot::new(Difficulty::Hard, 0).think(1, &world);
+        assert!(!keys.space && !keys.left && !keys.right);
+
+        // Straight ahead, it fires
+        world.place(2, Position::new(500.0, 300.0), 0.0);
+        assert!(Bot::new(Difficulty::Hard, 0).think(1, &world).space);
+    }
+
+    #[test]
+    fn bot_match_test() {
+        let mut world = World::new(3, 60, Settings::default());
+        let mut bots: HashMap<_, _> = [(1, Difficulty::Hard), (2, Difficulty::Easy)].into_iter()
+            .map(|(id, difficulty)| {
+                world.add_player(id);
+                (id, Bot::new(difficulty, id as u64))
+            })
+            .collect();
+
+        let mut ranking = Vec::new();
+        for _ in 0..60 * 300 {
+            let inputs = bots.iter_mut()
+                .map(|(id, bot)| (*id, bot.think(*id, &world)))
+                .collect();
+
+            for event in world.step(&inputs) {
+                if let Event::Finished { ranking: result } = event {
+                    ranking = result;
+                }
+            }
+            if world.is_finished() {
+                break;
+            }
+        }
+
+        assert_eq!(ranking.len(), 2);
+    }
+}
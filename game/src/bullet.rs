@@ -0,0 +1,51 @@
+use serde::Serialize;
+use vector::{Bounds, Position, Vec2};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bullet {
+    // Unique within the world along with ship ids, given by it when the
+    // bullet is fired
+    pub id: u64,
+    // Player number of the ship which fired the bullet
+    #[serde(skip)]
+    pub owner: u8,
+    // Id of the ship which fired the bullet
+    pub owner_id: u64,
+    color: String,
+    pub pos: Position,
+    // px/tick
+    speed: Vec2,
+}
+
+impl Bullet {
+    /// `speed` is in px/tick.
+    pub fn new(owner: u8, color: String, pos: Position, rad: f32, speed: f32) -> Self {
+        Self {
+            id: 0,
+            owner,
+            owner_id: 0,
+            color,
+            pos,
+            speed: Vec2::from_angle(rad) * speed,
+        }
+    }
+
+    pub fn color(&self) -> &str {
+        &self.color
+    }
+
+    /// Velocity in px/tick.
+    pub fn speed(&self) -> Vec2 {
+        self.speed
+    }
+
+    pub fn move_by_one_tick(&mut self) {
+        self.pos += self.speed;
+    }
+
+    /// Bullets do not wrap around, they die once they would leave `bounds`.
+    pub fn is_alive(&self, bounds: &Bounds) -> bool {
+        bounds.contains(self.pos + self.speed)
+    }
+}
@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+/// The simulated clock of a world, counted in fixed ticks.
+///
+/// Game timings are expressed as [`Duration`]s and converted to whole ticks,
+/// so a match only depends on the tick count and never on wall-clock time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clock {
+    pub tick: u64,
+    pub tick_rate: u32,
+}
+
+impl Clock {
+    pub fn new(tick_rate: u32) -> Self {
+        Self { tick: 0, tick_rate }
+    }
+
+    /// Length of one tick in seconds.
+    pub fn dt(&self) -> f32 {
+        1.0 / self.tick_rate as f32
+    }
+
+    /// Number of ticks closest to `duration`.
+    pub fn ticks(&self, duration: Duration) -> u64 {
+        (duration.as_secs_f64() * self.tick_rate as f64).round() as u64
+    }
+
+    /// Number of ticks elapsed since `tick`.
+    pub fn since(&self, tick: u64) -> u64 {
+        self.tick.saturating_sub(tick)
+    }
+
+    /// Simulated time elapsed since tick 0.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs(self.tick) / self.tick_rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_test() {
+        let clock = Clock::new(60);
+
+        assert_eq!(clock.ticks(Duration::from_millis(500)), 30);
+        assert_eq!(clock.ticks(Duration::from_secs(1)), 60);
+        assert_eq!(Clock::new(144).ticks(Duration::from_millis(500)), 72);
+    }
+}
@@ -0,0 +1,27 @@
+//! Colors of players in the `#rrggbb` form, as given by
+//! [`crate::player_color`], and their bytes.
+
+/// Parses a color in the `#rrggbb` form.
+pub fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    let [_, r, g, b] = value.to_be_bytes();
+
+    Some([r, g, b])
+}
+
+pub fn format_color([r, g, b]: [u8; 3]) -> String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_test() {
+        assert_eq!(parse_color("#0080ff"), Some([0x00, 0x80, 0xff]));
+        assert_eq!(format_color([0x00, 0x80, 0xff]), "#0080ff");
+        assert_eq!(parse_color("0080ff"), None);
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use crate::{keystate::KeyState, settings::Settings, state::WorldState, world::{Event, World}};
+
+// Rewards of a bullet hitting a ship, for its owner and for the ship
+pub const HIT_REWARD: f32 = 1.0;
+pub const HIT_PENALTY: f32 = -1.0;
+// Rewards at the end of a match, from the winner down to the first eliminated
+pub const WIN_REWARD: f32 = 5.0;
+pub const LOSE_REWARD: f32 = -5.0;
+
+/// The result of [`Env::step`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub observation: WorldState,
+    // Indexed like the actions
+    pub rewards: Vec<f32>,
+    pub done: bool,
+}
+
+/// A match as a reinforcement learning environment.
+///
+/// Players are indexed from 0, and the player at index `i` always gets the
+/// player number `i`. Matches start at once on [`Env::reset`], skipping the
+/// ready phase, and only move on [`Env::step`], so training runs as fast as
+/// the simulation does.
+#[derive(Debug)]
+pub struct Env {
+    players: u8,
+    tick_rate: u32,
+    settings: Settings,
+    world: World,
+}
+
+impl Env {
+    /// # Panics
+    ///
+    /// Panics if `players` is not between 2 and `settings.max_players`.
+    pub fn new(players: u8, tick_rate: u32, settings: Settings) -> Self {
+        assert!(
+            (2..=settings.max_players).contains(&players),
+            "players must be between 2 and max_players",
+        );
+
+        let mut env = Self {
+            players,
+            tick_rate,
+            settings,
+            world: World::new(0, tick_rate, settings),
+        };
+        env.reset(0);
+
+        env
+    }
+
+    /// Starts a new match seeded with `seed` and returns its first observation.
+    pub fn reset(&mut self, seed: u64) -> WorldState {
+        self.world = World::new(seed, self.tick_rate, self.settings);
+        for id in 0..self.players as usize {
+            self.world.add_player(id);
+        }
+
+        let ready: HashMap<_, _> = (0..self.players as usize)
+            .map(|id| (id, KeyState { space: true, ..KeyState::new() }))
+            .collect();
+        while !self.world.is_playing() {
+            self.world.step(&ready);
+        }
+
+        WorldState::new(&self.world)
+    }
+
+    /// Advances the match by one tick with the keys of each player.
+    ///
+    /// Players without an action press nothing. Once `done`, further steps
+    /// change nothing and give no rewards.
+    pub fn step(&mut self, actions: &[KeyState]) -> Step {
+        let inputs = actions.iter().copied().enumerate().collect();
+        let mut rewards = vec![0.0; self.players as usize];
+
+        for event in self.world.step(&inputs) {
+            match event {
+                Event::Hit { player_num, by } => {
+                    rewards[player_num as usize] += HIT_PENALTY;
+                    if by != player_num {
+                        rewards[by as usize] += HIT_REWARD;
+                    }
+                },
+                Event::Crashed { player_num, .. } => {
+                    rewards[player_num as usize] += HIT_PENALTY;
+                },
+                Event::Finished { ranking } => {
+                    for (rank, player_num) in ranking.iter().enumerate() {
+                        rewards[*player_num as usize] += rank_reward(rank, ranking.len());
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        Step {
+            observation: WorldState::new(&self.world),
+            rewards,
+            done: self.world.is_finished(),
+        }
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+}
+
+/// Reward of finishing at `rank` (0 for the winner) out of `players`, spread
+/// evenly from [`WIN_REWARD`] to [`LOSE_REWARD`].
+fn rank_reward(rank: usize, players: usize) -> f32 {
+    if players < 2 {
+        return WIN_REWARD;
+    }
+
+    let t = rank as f32 / (players - 1) as f32;
+    WIN_REWARD + (LOSE_REWARD - WIN_REWARD) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bot::{Bot, Difficulty};
+
+    use super::*;
+
+    #[test]
+    fn rank_reward_test() {
+        assert_eq!(rank_reward(0, 4), WIN_REWARD);
+        assert_eq!(rank_reward(3, 4), LOSE_REWARD);
+        assert_eq!(rank_reward(0, 2) + rank_reward(1, 2), 0.0);
+    }
+
+    #[test]
+    fn env_test() {
+        let settings = Settings { lives: 1, ..Default::default() };
+        let mut env = Env::new(2, 60, settings);
+
+        let observation = env.reset(7);
+        assert!(observation.is_playing);
+        assert_eq!(observation, env.reset(7));
+
+        let mut bots = [Bot::new(Difficulty::Hard, 1), Bot::new(Difficulty::Hard, 2)];
+        let mut total = [0.0; 2];
+        for _ in 0..60 * 300 {
+            let actions: Vec<_> = bots.iter_mut()
+                .enumerate()
+                .map(|(id, bot)| bot.think(id, env.world()))
+                .collect();
+
+            let step = env.step(&actions);
+            total[0] += step.rewards[0];
+            total[1] += step.rewards[1];
+            if step.done {
+                break;
+            }
+        }
+
+        // One life each, so the loser was hit once
+        assert!(env.world().is_finished());
+        let (winner, loser) = if total[0] > total[1] { (0, 1) } else { (1, 0) };
+        assert_eq!(total[loser], HIT_PENALTY + LOSE_REWARD);
+        assert!(total[winner] >= WIN_REWARD);
+
+        assert_eq!(env.step(&[]).rewards, vec![0.0, 0.0]);
+    }
+}
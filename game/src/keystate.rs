@@ -0,0 +1,41 @@
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct KeyState {
+    #[serde(rename = "ArrowLeft")]
+    pub left: bool,
+    #[serde(rename = "ArrowRight")]
+    pub right: bool,
+    #[serde(rename = "ArrowUp")]
+    pub up: bool,
+    #[serde(rename = " ")]
+    pub space: bool,
+}
+
+impl KeyState {
+    pub fn new() -> Self {
+        Self {
+            left: false,
+            right: false,
+            up: false,
+            space: false,
+        }
+    }
+
+    /// Packs the keys into the low four bits: left, right, up, space.
+    pub fn to_bits(self) -> u8 {
+        self.left as u8
+            | (self.right as u8) << 1
+            | (self.up as u8) << 2
+            | (self.space as u8) << 3
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        Self {
+            left: bits & 0b0001 != 0,
+            right: bits & 0b0010 != 0,
+            up: bits & 0b0100 != 0,
+            space: bits & 0b1000 != 0,
+        }
+    }
+}
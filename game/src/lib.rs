@@ -0,0 +1,29 @@
+//! Rules of the shooting game, independent of any transport.
+//!
+//! A [`World`] is advanced one tick at a time with the inputs of its players,
+//! and reports what happened as [`Event`]s. Worlds are deterministic: the
+//! same seed, tick rate, settings and inputs always play out the same match.
+
+pub mod bot;
+pub mod bullet;
+pub mod clock;
+pub mod color;
+pub mod env;
+pub mod keystate;
+pub mod replay;
+pub mod settings;
+pub mod ship;
+pub mod state;
+pub mod world;
+
+pub use bot::{Bot, Difficulty};
+pub use bullet::Bullet;
+pub use clock::Clock;
+pub use color::{format_color, parse_color};
+pub use env::{Env, Step};
+pub use keystate::KeyState;
+pub use replay::{Playback, Recorder, Replay};
+pub use settings::Settings;
+pub use ship::Ship;
+pub use state::WorldState;
+pub use world::{player_color, Event, World, COLOR_LIST, MAX_PLAYERS};
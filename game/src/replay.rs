@@ -0,0 +1,526 @@
+//! Recording and playback of matches.
+//!
+//! Since worlds are deterministic, a replay only stores the seed, the tick
+//! rate, the settings and every change applied to the world between ticks.
+//! Playing it back rebuilds the exact same match.
+//!
+//! # File format
+//!
+//! All integers are little-endian.
+//!
+//! | Size         | Field                                                 |
+//! |--------------|-------------------------------------------------------|
+//! | 4            | Magic, `SHRP`                                         |
+//! | 2            | Format version, [`VERSION`]                           |
+//! | 2 + 2        | Width and height of the field, from the settings      |
+//! | 4            | Tick rate in Hz                                       |
+//! | 8            | Seed of the world                                     |
+//! | 8            | Tick the match ended at                               |
+//! | 16           | Settings: lives (1), player cap (1), charge and       |
+//! |              | respawn time in ms (2 + 2), acceleration and bullet   |
+//! |              | speed as `f32` (4 + 4), max rewind in ms (2)          |
+//! | 2            | Number of players in the roster                       |
+//! | 12 × players | Player id (8), player number (1), color as RGB (3)    |
+//! | 1            | Number of players in the ranking                      |
+//! | 1 × players  | Player numbers, winner first                          |
+//! | 4            | Number of records                                     |
+//! | 8 × records  | Ticks since the previous record (4), roster slot (2), |
+//! |              | kind (1), keys (1)                                    |
+//!
+//! The kind of a record is `0` when the player got a ship, `1` when they
+//! left, `2` when their keys changed and `3` when their bullet of the tick
+//! was rewound. Keys are packed as in [`KeyState::to_bits`], and rewinds
+//! take their number of ticks in place of the keys.
+//!
+//! The version changes along with the rules of the game too, as files of
+//! an older version would play back into other matches than the ones they
+//! recorded. Such files are rejected.
+
+use std::{collections::{BTreeMap, HashMap}, io::{self, Read, Write}};
+
+use crate::{
+    color::parse_color, keystate::KeyState, settings::Settings, ship::Ship, world::{Event, World},
+};
+
+const MAGIC: &[u8; 4] = b"SHRP";
+// 5: ships are hit across the edges of the field
+// 6: bullets hit along their way, and ships crash into each other
+pub const VERSION: u16 = 6;
+// The oldest version played by the same rules
+const MIN_VERSION: u16 = 6;
+
+const JOIN: u8 = 0;
+const LEAVE: u8 = 1;
+const INPUT: u8 = 2;
+const REWIND: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Join,
+    Leave,
+    Input(KeyState),
+    // Ticks, see `World::rewind`
+    Rewind(u8),
+}
+
+/// An action of the player in `slot` of the roster, applied before the
+/// world steps past `tick`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Record {
+    pub tick: u64,
+    pub slot: u16,
+    pub action: Action,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Player {
+    pub id: usize,
+    pub player_num: u8,
+    pub color: [u8; 3],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub tick_rate: u32,
+    pub seed: u64,
+    pub end_tick: u64,
+    pub settings: Settings,
+    // Players in order of their first join, indexed by slot
+    pub roster: Vec<Player>,
+    // [PlayerNum], winner first
+    pub ranking: Vec<u8>,
+    pub records: Vec<Record>,
+}
+
+/// What a replay plays out to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    // [PlayerNum], winner first. Empty if the match did not finish.
+    pub ranking: Vec<u8>,
+    pub ticks: u64,
+    // (PlayerNum, count)
+    pub shots: BTreeMap<u8, u32>,
+    pub hits: BTreeMap<u8, u32>,
+}
+
+impl Replay {
+    pub fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_all(&self.settings.width.to_le_bytes())?;
+        w.write_all(&self.settings.height.to_le_bytes())?;
+        w.write_all(&self.tick_rate.to_le_bytes())?;
+        w.write_all(&self.seed.to_le_bytes())?;
+        w.write_all(&self.end_tick.to_le_bytes())?;
+
+        let settings = &self.settings;
+        let lives = u8::try_from(settings.lives).map_err(|_| invalid_data("Too many lives"))?;
+        let charge_time = u16::try_from(settings.charge_time)
+            .map_err(|_| invalid_data("Charge time is too long"))?;
+        let respawn_time = u16::try_from(settings.respawn_time)
+            .map_err(|_| invalid_data("Respawn time is too long"))?;
+        w.write_all(&[lives, settings.max_players])?;
+        w.write_all(&charge_time.to_le_bytes())?;
+        w.write_all(&respawn_time.to_le_bytes())?;
+        w.write_all(&settings.acceleration.to_le_bytes())?;
+        w.write_all(&settings.bullet_speed.to_le_bytes())?;
+        let max_rewind = u16::try_from(settings.max_rewind)
+            .map_err(|_| invalid_data("Max rewind is too long"))?;
+        w.write_all(&max_rewind.to_le_bytes())?;
+
+        w.write_all(&(self.roster.len() as u16).to_le_bytes())?;
+        for player in &self.roster {
+            w.write_all(&(player.id as u64).to_le_bytes())?;
+            w.write_all(&[player.player_num])?;
+            w.write_all(&player.color)?;
+        }
+
+        w.write_all(&[self.ranking.len() as u8])?;
+        w.write_all(&self.ranking)?;
+
+        w.write_all(&(self.records.len() as u32).to_le_bytes())?;
+        let mut last_tick = 0;
+        for record in &self.records {
+            let (kind, keys) = match record.action {
+                Action::Join => (JOIN, 0),
+                Action::Leave => (LEAVE, 0),
+                Action::Input(key_state) => (INPUT, key_state.to_bits()),
+                Action::Rewind(ticks) => (REWIND, ticks),
+            };
+
+            let delta = record.tick.checked_sub(last_tick)
+                .and_then(|delta| u32::try_from(delta).ok())
+                .ok_or_else(|| invalid_data("Records are out of order or too far apart"))?;
+            w.write_all(&delta.to_le_bytes())?;
+            w.write_all(&record.slot.to_le_bytes())?;
+            w.write_all(&[kind, keys])?;
+            last_tick = record.tick;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_from(r: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("Not a replay file"));
+        }
+
+        let version = u16::from_le_bytes(read_array(r)?);
+        if !(MIN_VERSION..=VERSION).contains(&version) {
+            return Err(invalid_data(&format!("Unsupported replay version {version}")));
+        }
+
+        let width = u16::from_le_bytes(read_array(r)?);
+        let height = u16::from_le_bytes(read_array(r)?);
+        let tick_rate = u32::from_le_bytes(read_array(r)?);
+        if tick_rate == 0 {
+            return Err(invalid_data("Tick rate must not be zero"));
+        }
+        let seed = u64::from_le_bytes(read_array(r)?);
+        let end_tick = u64::from_le_bytes(read_array(r)?);
+
+        let [lives, max_players] = read_array(r)?;
+        let settings = Settings {
+            lives: lives as u32,
+            max_players,
+            charge_time: u16::from_le_bytes(read_array(r)?) as u32,
+            respawn_time: u16::from_le_bytes(read_array(r)?) as u32,
+            acceleration: f32::from_le_bytes(read_array(r)?),
+            bullet_speed: f32::from_le_bytes(read_array(r)?),
+            max_rewind: u16::from_le_bytes(read_array(r)?) as u32,
+            width,
+            height,
+        };
+
+        let roster_len = u16::from_le_bytes(read_array(r)?);
+        let roster = (0..roster_len)
+            .map(|_| {
+                let id = u64::from_le_bytes(read_array(r)?) as usize;
+                let [player_num] = read_array(r)?;
+                let color = read_array(r)?;
+                Ok(Player { id, player_num, color })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let [ranking_len] = read_array(r)?;
+        let mut ranking = vec![0; ranking_len as usize];
+        r.read_exact(&mut ranking)?;
+
+        let records_len = u32::from_le_bytes(read_array(r)?);
+        let mut records = Vec::new();
+        let mut tick = 0;
+        for _ in 0..records_len {
+            tick += u32::from_le_bytes(read_array(r)?) as u64;
+            let slot = u16::from_le_bytes(read_array(r)?);
+            let [kind, keys] = read_array(r)?;
+
+            if slot as usize >= roster.len() {
+                return Err(invalid_data("Record refers to an unknown player"));
+            }
+            let action = match kind {
+                JOIN => Action::Join,
+                LEAVE => Action::Leave,
+                INPUT => Action::Input(KeyState::from_bits(keys)),
+                REWIND => Action::Rewind(keys),
+                _ => return Err(invalid_data("Unknown record kind")),
+            };
+
+            records.push(Record { tick, slot, action });
+        }
+
+        Ok(Self { tick_rate, seed, end_tick, settings, roster, ranking, records })
+    }
+
+    /// Checks that the replay can be played back by this version of the game.
+    pub fn validate(&self) -> Result<(), String> {
+        self.settings.validate()?;
+
+        if let Some(record) = self.records.last() {
+            if record.tick > self.end_tick {
+                return Err(format!("Record at tick {} after the end", record.tick));
+            }
+        }
+
+        let mut joined = vec![false; self.roster.len()];
+        for record in &self.records {
+            let joined = &mut joined[record.slot as usize];
+            match record.action {
+                Action::Join if *joined => {
+                    return Err(format!("Slot {} joined twice at tick {}", record.slot, record.tick));
+                },
+                Action::Join => *joined = true,
+                _ if !*joined => {
+                    return Err(format!("Slot {} acted before joining at tick {}", record.slot, record.tick));
+                },
+                Action::Leave => *joined = false,
+                Action::Input(_) | Action::Rewind(_) => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Plays the replay back headlessly.
+    pub fn simulate(&self) -> Summary {
+        let mut summary = Summary {
+            ranking: Vec::new(),
+            ticks: 0,
+            shots: BTreeMap::new(),
+            hits: BTreeMap::new(),
+        };
+
+        let mut playback = Playback::new(self.clone());
+        while let Some(events) = playback.step() {
+            for event in events {
+                match event {
+                    Event::Fired { player_num } => *summary.shots.entry(player_num).or_default() += 1,
+                    Event::Hit { by, .. } => *summary.hits.entry(by).or_default() += 1,
+                    Event::Finished { ranking } => summary.ranking = ranking,
+                    _ => (),
+                }
+            }
+        }
+
+        summary.ticks = playback.world().clock().tick;
+        summary
+    }
+}
+
+fn read_array<const N: usize>(r: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Records what a room applies to its world, to be turned into a [`Replay`]
+/// when the match ends.
+#[derive(Debug)]
+pub struct Recorder {
+    replay: Replay,
+    // (PlayerID, KeyState)
+    keystates: HashMap<usize, KeyState>,
+}
+
+impl Recorder {
+    pub fn new(seed: u64, tick_rate: u32, settings: Settings) -> Self {
+        Self {
+            replay: Replay {
+                tick_rate,
+                seed,
+                end_tick: 0,
+                settings,
+                roster: Vec::new(),
+                ranking: Vec::new(),
+                records: Vec::new(),
+            },
+            keystates: HashMap::new(),
+        }
+    }
+
+    fn push(&mut self, tick: u64, id: usize, action: Action) {
+        let Some(slot) = self.replay.roster.iter().position(|player| player.id == id) else {
+            return;
+        };
+
+        self.replay.records.push(Record { tick, slot: slot as u16, action });
+    }
+
+    /// Records that the player got `ship` at `tick`.
+    pub fn join(&mut self, tick: u64, id: usize, ship: &Ship) {
+        self.replay.roster.push(Player {
+            id,
+            player_num: ship.player_num,
+            color: parse_color(ship.color()).unwrap_or_default(),
+        });
+        self.keystates.insert(id, KeyState::new());
+        self.push(tick, id, Action::Join);
+    }
+
+    pub fn leave(&mut self, tick: u64, id: usize) {
+        if self.keystates.remove(&id).is_some() {
+            self.push(tick, id, Action::Leave);
+        }
+    }
+
+    /// Records the keys of the player if they changed.
+    pub fn input(&mut self, tick: u64, id: usize, state: KeyState) {
+        match self.keystates.get_mut(&id) {
+            Some(key_state) if *key_state != state => *key_state = state,
+            _ => return,
+        }
+
+        self.push(tick, id, Action::Input(state));
+    }
+
+    /// Records that the bullet the player fires at `tick` is rewound.
+    pub fn rewind(&mut self, tick: u64, id: usize, ticks: u8) {
+        if self.keystates.contains_key(&id) {
+            self.push(tick, id, Action::Rewind(ticks));
+        }
+    }
+
+    /// Builds the replay of a match which ended at `end_tick` with `ranking`.
+    pub fn finish(&self, end_tick: u64, ranking: &[u8]) -> Replay {
+        Replay {
+            end_tick,
+            ranking: ranking.to_vec(),
+            ..self.replay.clone()
+        }
+    }
+}
+
+/// Rebuilds the world of a [`Replay`] tick by tick.
+#[derive(Debug)]
+pub struct Playback {
+    replay: Replay,
+    world: World,
+    // (PlayerID, KeyState)
+    inputs: HashMap<usize, KeyState>,
+    cursor: usize,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            world: World::new(replay.seed, replay.tick_rate, replay.settings),
+            replay,
+            inputs: HashMap::new(),
+            cursor: 0,
+        }
+    }
+
+    /// Applies the records of the current tick and steps the world, or
+    /// returns `None` once the replay has ended.
+    pub fn step(&mut self) -> Option<Vec<Event>> {
+        let tick = self.world.clock().tick;
+        if tick >= self.replay.end_tick {
+            return None;
+        }
+
+        while let Some(record) = self.replay.records.get(self.cursor) {
+            if record.tick > tick {
+                break;
+            }
+
+            let id = self.replay.roster[record.slot as usize].id;
+            match record.action {
+                Action::Join => {
+                    self.world.add_player(id);
+                    self.inputs.insert(id, KeyState::new());
+                },
+                Action::Leave => {
+                    self.world.remove_player(id);
+                    self.inputs.remove(&id);
+                },
+                Action::Input(state) => {
+                    self.inputs.insert(id, state);
+                },
+                Action::Rewind(ticks) => {
+                    self.world.rewind(id, ticks as u64);
+                },
+            }
+            self.cursor += 1;
+        }
+
+        Some(self.world.step(&self.inputs))
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record_match() -> (Replay, World) {
+        let settings = Settings { lives: 2, charge_time: 300, max_rewind: 100, ..Default::default() };
+        let mut world = World::new(7, 60, settings);
+        let mut recorder = Recorder::new(world.seed(), world.clock().tick_rate, settings);
+        let mut inputs = HashMap::new();
+
+        for id in [30, 10, 20] {
+            world.add_player(id);
+            recorder.join(world.clock().tick, id, world.ship(id).unwrap());
+            inputs.insert(id, KeyState::new());
+        }
+
+        let mut ranking = Vec::new();
+        for tick in 0..36000_usize {
+            if tick == 10 {
+                world.remove_player(30);
+                recorder.leave(world.clock().tick, 30);
+                inputs.remove(&30);
+            }
+
+            for (i, id) in [10, 20].into_iter().enumerate() {
+                let state = KeyState {
+                    left: (tick / 45 + i) % 3 == 0,
+                    right: false,
+                    up: (tick / 20 + i) % 2 == 0,
+                    space: true,
+                };
+                recorder.input(world.clock().tick, id, state);
+                inputs.insert(id, state);
+            }
+
+            if tick % 50 == 0 {
+                recorder.rewind(world.clock().tick, 10, 3);
+                world.rewind(10, 3);
+            }
+
+            for event in world.step(&inputs) {
+                if let Event::Finished { ranking: result } = event {
+                    ranking = result;
+                }
+            }
+            if world.is_finished() {
+                break;
+            }
+        }
+
+        assert!(world.is_playing());
+        (recorder.finish(world.clock().tick, &ranking), world)
+    }
+
+    #[test]
+    fn encode_test() {
+        let (replay, _) = record_match();
+
+        let mut buf = Vec::new();
+        replay.write_to(&mut buf).unwrap();
+
+        assert_eq!(Replay::read_from(&mut buf.as_slice()).unwrap(), replay);
+        assert!(Replay::read_from(&mut &buf[..buf.len() - 1]).is_err());
+
+        // Recorded under older rules
+        buf[4..6].copy_from_slice(&(MIN_VERSION - 1).to_le_bytes());
+        assert!(Replay::read_from(&mut buf.as_slice()).is_err());
+    }
+
+    #[test]
+    fn playback_test() {
+        let (replay, world) = record_match();
+        assert!(replay.validate().is_ok());
+
+        let mut playback = Playback::new(replay.clone());
+        while playback.step().is_some() {}
+
+        assert_eq!(playback.world().clock(), world.clock());
+        assert!(playback.world().ships().eq(world.ships()));
+        assert_eq!(playback.world().bullets(), world.bullets());
+        assert!(!replay.ranking.is_empty());
+        assert_eq!(replay.simulate().ranking, replay.ranking);
+    }
+}
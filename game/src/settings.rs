@@ -0,0 +1,133 @@
+use std::{ops::RangeInclusive, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use vector::Bounds;
+
+use crate::world::MAX_PLAYERS;
+
+pub const LIVES_RANGE: RangeInclusive<u32> = 1..=9;
+pub const PLAYERS_RANGE: RangeInclusive<u8> = 2..=MAX_PLAYERS as u8;
+// ms
+pub const CHARGE_TIME_RANGE: RangeInclusive<u32> = 100..=3000;
+pub const RESPAWN_TIME_RANGE: RangeInclusive<u32> = 200..=5000;
+// Ships and bullets must not move more than the wrapping margin of the
+// field in one tick, even at the lowest tick rate of 30 Hz. A ship
+// accelerating forever tops out at `acceleration / DECEL_FACTOR` px/s.
+// px/s^2
+pub const ACCELERATION_RANGE: RangeInclusive<f32> = 36.0..=135.0;
+// px/s
+pub const BULLET_SPEED_RANGE: RangeInclusive<f32> = 120.0..=450.0;
+// ms, 0 turns lag compensation off. Kept within 255 ticks at 240 Hz.
+pub const MAX_REWIND_RANGE: RangeInclusive<u32> = 0..=250;
+// px, wide enough to line up a full room and small enough for the positions
+// of binary frames
+pub const SIZE_RANGE: RangeInclusive<u16> = 480..=960;
+
+/// Rules a room is created with.
+///
+/// Missing fields take their default values when deserialized, so a host
+/// only needs to give what they want to change.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct Settings {
+    pub lives: u32,
+    pub max_players: u8,
+    // ms
+    pub charge_time: u32,
+    pub respawn_time: u32,
+    // px/s^2
+    pub acceleration: f32,
+    // px/s
+    pub bullet_speed: f32,
+    // ms, how far back bullets of lagging players may be fired from
+    pub max_rewind: u32,
+    // px, the size of the field
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            lives: 3,
+            max_players: 4,
+            charge_time: 500,
+            respawn_time: 1000,
+            acceleration: 108.0,
+            bullet_speed: 420.0,
+            max_rewind: 0,
+            width: 600,
+            height: 600,
+        }
+    }
+}
+
+impl Settings {
+    /// Checks that every value is within its range.
+    pub fn validate(&self) -> Result<(), String> {
+        check("lives", self.lives, LIVES_RANGE)?;
+        check("maxPlayers", self.max_players, PLAYERS_RANGE)?;
+        check("chargeTime", self.charge_time, CHARGE_TIME_RANGE)?;
+        check("respawnTime", self.respawn_time, RESPAWN_TIME_RANGE)?;
+        check("acceleration", self.acceleration, ACCELERATION_RANGE)?;
+        check("bulletSpeed", self.bullet_speed, BULLET_SPEED_RANGE)?;
+        check("maxRewind", self.max_rewind, MAX_REWIND_RANGE)?;
+        check("width", self.width, SIZE_RANGE)?;
+        check("height", self.height, SIZE_RANGE)?;
+
+        Ok(())
+    }
+
+    pub fn charge_time(&self) -> Duration {
+        Duration::from_millis(self.charge_time as u64)
+    }
+
+    pub fn respawn_time(&self) -> Duration {
+        Duration::from_millis(self.respawn_time as u64)
+    }
+
+    pub fn max_rewind(&self) -> Duration {
+        Duration::from_millis(self.max_rewind as u64)
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        Bounds::new(self.width as f32, self.height as f32)
+    }
+}
+
+fn check<T>(name: &str, value: T, range: RangeInclusive<T>) -> Result<(), String>
+where
+    T: PartialOrd + std::fmt::Display,
+{
+    if range.contains(&value) {
+        Ok(())
+    } else {
+        Err(format!("{name} must be between {} and {}", range.start(), range.end()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_test() {
+        assert!(Settings::default().validate().is_ok());
+        assert!(Settings { lives: 1, max_players: 6, ..Default::default() }.validate().is_ok());
+
+        assert!(Settings { lives: 0, ..Default::default() }.validate().is_err());
+        assert!(Settings { max_players: 1, ..Default::default() }.validate().is_err());
+        assert!(Settings { bullet_speed: f32::NAN, ..Default::default() }.validate().is_err());
+        assert!(Settings { max_rewind: 300, ..Default::default() }.validate().is_err());
+        assert!(Settings { width: 960, height: 480, ..Default::default() }.validate().is_ok());
+        assert!(Settings { width: 2000, ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn deserialize_test() {
+        let settings: Settings = serde_json::from_str(r#"{"lives":1,"maxPlayers":6}"#).unwrap();
+        assert_eq!(settings, Settings { lives: 1, max_players: 6, ..Default::default() });
+
+        assert!(serde_json::from_str::<Settings>(r#"{"life":1}"#).is_err());
+    }
+}
@@ -0,0 +1,211 @@
+use std::f32::consts::PI;
+
+use rand::Rng;
+use vector::{Bounds, Position, Vec2};
+use serde::Serialize;
+
+use crate::{bullet::Bullet, clock::Clock, keystate::KeyState, settings::Settings};
+
+// Rates are per second so that the tick rate does not change game feel.
+// The rest of the rules come from the room's `Settings`.
+// rad/s
+pub const ROTATE_SPEED: f32 = 4.2;
+// 1/s, the speed decays by exp(-DECEL_FACTOR * t)
+pub const DECEL_FACTOR: f32 = 0.3;
+pub const HIT_AREA_RADIUS: f32 = 12.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ship {
+    // Unique within the world along with bullet ids, given by it when the
+    // ship is added
+    pub id: u64,
+    pub player_num: u8,
+    color: String,
+    pos: Position,
+    rad: f32,
+    // px/tick
+    #[serde(skip)]
+    speed: Vec2,
+    // How far the ship moved in the last tick, for swept collisions
+    #[serde(skip)]
+    last_move: Vec2,
+    #[serde(skip)]
+    lives: u32,
+    is_alive: bool,
+    is_accelerating: bool,
+    pub is_ready: bool,
+    // Ticks of the world's clock
+    #[serde(skip)]
+    last_fire_tick: u64,
+    #[serde(skip)]
+    last_hit_tick: u64,
+}
+
+impl Ship {
+    pub fn new(player_num: u8, color: String, pos: Position, rad: f32, lives: u32, now: u64) -> Self {
+        Self {
+            id: 0,
+            player_num,
+            color,
+            pos,
+            rad,
+            speed: Vec2::ZERO,
+            last_move: Vec2::ZERO,
+            lives,
+            is_alive: true,
+            is_accelerating: false,
+            is_ready: false,
+            last_fire_tick: now,
+            last_hit_tick: now,
+        }
+    }
+
+    pub fn process_one_tick(
+        &mut self,
+        key_state: &KeyState,
+        clock: &Clock,
+        settings: &Settings,
+        rng: &mut impl Rng,
+    ) {
+        self.is_accelerating = key_state.up;
+        self.last_move = Vec2::ZERO;
+        if self.is_alive {
+            self.move_by_one_tick(key_state, clock.dt(), settings);
+        } else if clock.since(self.last_hit_tick) > clock.ticks(settings.respawn_time()) {
+            self.lives -= 1;
+            self.is_alive = true;
+            self.put_on_random_place(rng, &settings.bounds());
+        }
+    }
+
+    fn move_by_one_tick(&mut self, key_state: &KeyState, dt: f32, settings: &Settings) {
+        if key_state.up {
+            self.speed += Vec2::from_angle(self.rad) * (settings.acceleration * dt * dt);
+        }
+
+        self.speed *= (-DECEL_FACTOR * dt).exp();
+
+        self.pos = settings.bounds().wrap(self.pos + self.speed);
+        self.last_move = self.speed;
+
+        if key_state.right {
+            self.rad += ROTATE_SPEED * dt;
+        }
+        if key_state.left {
+            self.rad -= ROTATE_SPEED * dt;
+        }
+    }
+
+    pub fn put_on_random_place(&mut self, rng: &mut impl Rng, bounds: &Bounds) {
+        self.pos.x = rng.random_range(0.0 .. bounds.width);
+        self.pos.y = rng.random_range(0.0 .. bounds.height);
+        self.rad   = rng.random_range(0.0 .. PI * 2.0);
+        self.speed = Vec2::ZERO;
+    }
+
+    pub fn fire(&mut self, clock: &Clock, settings: &Settings) -> Option<Bullet> {
+        if self.is_alive && clock.since(self.last_fire_tick) > clock.ticks(settings.charge_time()) {
+            self.last_fire_tick = clock.tick;
+
+            let head = settings.bounds().wrap(self.pos + Vec2::from_angle(self.rad) * 15.0);
+            let speed = settings.bullet_speed * clock.dt();
+            let mut bullet = Bullet::new(self.player_num, self.color.clone(), head, self.rad, speed);
+            bullet.owner_id = self.id;
+            Some(bullet)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if the bullet destroyed the ship.
+    ///
+    /// Both have just moved, so the bullet hits if it touched the ship
+    /// anywhere on its way, however fast it flies.
+    pub fn collision_process(&mut self, bullet: &Bullet, clock: &Clock, bounds: &Bounds) -> bool {
+        self.sweep_process(self.pos - self.last_move, self.last_move, bullet, clock, bounds)
+    }
+
+    /// Like [`Ship::collision_process`], with the ship standing at `pos`
+    /// instead, where it was when a rewound bullet was fired.
+    pub fn collision_process_at(&mut self, pos: Position, bullet: &Bullet, clock: &Clock, bounds: &Bounds) -> bool {
+        self.sweep_process(pos, Vec2::ZERO, bullet, clock, bounds)
+    }
+
+    /// Tests the bullet against the ship moving from `from` by `motion`
+    /// during the tick the bullet moved.
+    fn sweep_process(&mut self, from: Position, motion: Vec2, bullet: &Bullet, clock: &Clock, bounds: &Bounds) -> bool {
+        let speed = bullet.speed();
+        if bounds.sweep(bullet.pos - speed, speed - motion, from, HIT_AREA_RADIUS).is_some() {
+            self.destroy(clock)
+        } else {
+            false
+        }
+    }
+
+    /// Returns `true` if both ships are alive and touched each other anywhere
+    /// on their ways through the last tick.
+    pub fn touches(&self, other: &Ship, bounds: &Bounds) -> bool {
+        let from = self.pos - self.last_move;
+        let other_from = other.pos - other.last_move;
+
+        self.is_alive && other.is_alive
+            && bounds.sweep(from, self.last_move - other.last_move, other_from, HIT_AREA_RADIUS * 2.0).is_some()
+    }
+
+    /// Destroys the ship, as a bullet or a crash does. Returns `true` if it
+    /// was alive.
+    pub fn destroy(&mut self, clock: &Clock) -> bool {
+        let was_alive = self.is_alive;
+        self.is_alive = false;
+
+        // Continues an invicible time even if a player is dead.
+        self.last_hit_tick = clock.tick;
+
+        was_alive
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.lives == 0
+    }
+
+    pub fn color(&self) -> &str {
+        &self.color
+    }
+
+    pub fn pos(&self) -> Position {
+        self.pos
+    }
+
+    pub fn rad(&self) -> f32 {
+        self.rad
+    }
+
+    /// Velocity in px/tick.
+    pub fn speed(&self) -> Vec2 {
+        self.speed
+    }
+
+    /// How far the ship moved in the last tick, across the edges of the field.
+    pub fn last_move(&self) -> Vec2 {
+        self.last_move
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.is_alive
+    }
+
+    pub fn is_accelerating(&self) -> bool {
+        self.is_accelerating
+    }
+
+    pub fn lives(&self) -> u32 {
+        self.lives
+    }
+
+    /// Ticks until the ship can fire again.
+    pub fn fire_cooldown(&self, clock: &Clock, settings: &Settings) -> u64 {
+        // `fire` needs strictly more than the charge time to have passed
+        (clock.ticks(settings.charge_time()) + 1).saturating_sub(clock.since(self.last_fire_tick))
+    }
+}
@@ -0,0 +1,130 @@
+use serde::Serialize;
+use vector::{Position, Vec2};
+
+use crate::{bullet::Bullet, clock::Clock, settings::Settings, ship::Ship, world::World};
+
+/// Everything a program playing the world may need to know about it, unlike
+/// the frames sent to browsers which only hold what is drawn.
+///
+/// Speeds are in px/tick, `tick_rate` converts them to px/s.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorldState {
+    pub tick: u64,
+    pub tick_rate: u32,
+    pub is_playing: bool,
+    pub is_finished: bool,
+    pub settings: Settings,
+    pub ships: Vec<ShipState>,
+    pub bullets: Vec<BulletState>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShipState {
+    pub id: u64,
+    pub player_num: u8,
+    pub color: String,
+    pub pos: Position,
+    pub rad: f32,
+    pub speed: Vec2,
+    pub lives: u32,
+    pub is_alive: bool,
+    pub is_accelerating: bool,
+    pub is_ready: bool,
+    // Ticks until the ship can fire again
+    pub fire_cooldown: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulletState {
+    pub id: u64,
+    // Player number of the ship which fired the bullet
+    pub owner: u8,
+    // Id of the ship which fired the bullet
+    pub owner_id: u64,
+    pub pos: Position,
+    pub speed: Vec2,
+}
+
+impl WorldState {
+    pub fn new(world: &World) -> Self {
+        let clock = world.clock();
+        let settings = *world.settings();
+
+        Self {
+            tick: clock.tick,
+            tick_rate: clock.tick_rate,
+            is_playing: world.is_playing(),
+            is_finished: world.is_finished(),
+            settings,
+            ships: world.ships()
+                .map(|(_, ship)| ShipState::new(ship, &clock, &settings))
+                .collect(),
+            bullets: world.bullets().iter().map(BulletState::from).collect(),
+        }
+    }
+}
+
+impl ShipState {
+    pub fn new(ship: &Ship, clock: &Clock, settings: &Settings) -> Self {
+        Self {
+            id: ship.id,
+            player_num: ship.player_num,
+            color: ship.color().to_string(),
+            pos: ship.pos(),
+            rad: ship.rad(),
+            speed: ship.speed(),
+            lives: ship.lives(),
+            is_alive: ship.is_alive(),
+            is_accelerating: ship.is_accelerating(),
+            is_ready: ship.is_ready,
+            fire_cooldown: ship.fire_cooldown(clock, settings),
+        }
+    }
+}
+
+impl From<&Bullet> for BulletState {
+    fn from(bullet: &Bullet) -> Self {
+        Self {
+            id: bullet.id,
+            owner: bullet.owner,
+            owner_id: bullet.owner_id,
+            pos: bullet.pos,
+            speed: bullet.speed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::keystate::KeyState;
+
+    use super::*;
+
+    #[test]
+    fn state_test() {
+        let mut world = World::new(0, 60, Settings { lives: 2, ..Default::default() });
+        world.add_player(10);
+        world.add_player(20);
+
+        let fire = KeyState { space: true, ..KeyState::new() };
+        let inputs = HashMap::from([(10, fire), (20, fire)]);
+        while !world.is_playing() {
+            world.step(&inputs);
+        }
+        world.step(&inputs);
+
+        let state = WorldState::new(&world);
+        assert_eq!(state.tick, world.clock().tick);
+        assert_eq!(state.ships.len(), 2);
+        assert!(state.ships.iter().all(|ship| ship.lives == 2 && ship.fire_cooldown > 0));
+        assert_eq!(state.bullets.len(), 2);
+
+        let json = serde_json::to_value(&state).unwrap();
+        assert!(json["ships"][0]["speed"].is_object());
+    }
+}
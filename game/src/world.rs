@@ -0,0 +1,709 @@
+use std::{collections::{BTreeMap, HashMap, VecDeque}, f32::consts::PI, time::Duration};
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use vector::{Bounds, Grid, Position};
+
+use crate::{
+    bullet::Bullet, clock::Clock, color::format_color, keystate::KeyState, settings::Settings,
+    ship::{Ship, HIT_AREA_RADIUS},
+};
+
+pub const MAX_PLAYERS: usize = 16;
+// Colors of the first players, the rest are generated by `player_color`
+pub const COLOR_LIST: [&str; 6] = [
+    "#00ff00", // Green
+    "#ff0000", // Red
+    "#0080ff", // Blue
+    "#ffff00", // Yellow
+    "#ff00ff", // Magenta
+    "#00ffff", // Cyan
+];
+// Ships waiting for the match are lined up in rows of this many
+const SPAWN_ROW_LEN: usize = 8;
+const SPAWN_ROW_GAP: f32 = 120.0;
+pub const START_DELAY: Duration = Duration::from_secs(1);
+// px, wider than a ship along with the way of the fastest bullet in a tick
+const GRID_CELL_SIZE: f32 = 60.0;
+
+/// Color of the player in the `#rrggbb` form.
+///
+/// Players beyond [`COLOR_LIST`] get pale colors whose hues are a golden
+/// angle apart, so that any number of players stay distinguishable.
+pub fn player_color(player_num: u8) -> String {
+    if let Some(color) = COLOR_LIST.get(player_num as usize) {
+        return color.to_string();
+    }
+
+    let hue = (player_num as f32 * 137.508) % 360.0;
+    let (saturation, value) = (0.55, 1.0);
+
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 / 60 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+    let to_byte = |c: f32| ((c + m) * 255.0).round() as u8;
+
+    format_color([to_byte(r), to_byte(g), to_byte(b)])
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// All ships got ready and were put on the field.
+    Started,
+    /// A ship fired a bullet.
+    Fired {
+        player_num: u8,
+    },
+    /// A bullet of `by` destroyed the ship of `player_num`.
+    Hit {
+        player_num: u8,
+        by: u8,
+    },
+    /// The ship of `player_num` ran into the ship of `with`, which is
+    /// destroyed as well.
+    Crashed {
+        player_num: u8,
+        with: u8,
+    },
+    /// A ship lost its last life.
+    Eliminated {
+        player_id: usize,
+        player_num: u8,
+    },
+    /// Only one ship is left. `ranking` lists player numbers, winner first.
+    Finished {
+        ranking: Vec<u8>,
+    },
+}
+
+/// A single match: the ships of its players, their bullets and the
+/// ready/ranking state.
+///
+/// The world keeps its own simulated clock, which only moves when
+/// [`World::step`] is called, and draws every random number from a generator
+/// seeded at creation. Ships are kept ordered by player id so that iteration
+/// order never depends on hashing.
+#[derive(Debug)]
+pub struct World {
+    seed: u64,
+    rng: ChaCha8Rng,
+    clock: Clock,
+    settings: Settings,
+    // (PlayerID, Ship)
+    ships: BTreeMap<usize, Ship>,
+    bullets: Vec<Bullet>,
+    // Bullets by where they moved in the last tick, refilled every step
+    grid: Grid,
+    // Ids of ships and bullets are never reused within a world
+    next_entity_id: u64,
+    // Positions of ships at the last ticks of the match, the newest last,
+    // kept for `Settings::max_rewind`
+    history: VecDeque<Vec<(usize, Position)>>,
+    // (PlayerID, Ticks), rewinds for the coming step
+    rewinds: HashMap<usize, u64>,
+    player_num_pool: VecDeque<u8>,
+    is_playing: bool,
+    is_finished: bool,
+    all_ready_tick: Option<u64>,
+    // [PlayerNum], the first eliminated first
+    ranking: Vec<u8>,
+}
+
+impl World {
+    pub fn new(seed: u64, tick_rate: u32, settings: Settings) -> Self {
+        Self {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            clock: Clock::new(tick_rate),
+            settings,
+            ships: BTreeMap::new(),
+            bullets: Vec::new(),
+            grid: Grid::new(settings.bounds(), GRID_CELL_SIZE),
+            next_entity_id: 0,
+            history: VecDeque::new(),
+            rewinds: HashMap::new(),
+            player_num_pool: (0..settings.max_players).collect(),
+            is_playing: false,
+            is_finished: false,
+            all_ready_tick: None,
+            ranking: Vec::new(),
+        }
+    }
+
+    /// Adds a ship for the player and returns its player number, or `None`
+    /// if the match has started or the room is full.
+    pub fn add_player(&mut self, id: usize) -> Option<u8> {
+        if self.is_playing || self.ships.contains_key(&id) {
+            return None;
+        }
+
+        let player_num = self.player_num_pool.pop_front()?;
+        let mut ship = Ship::new(
+            player_num,
+            player_color(player_num),
+            self.spawn_pos(player_num),
+            -PI / 2.0,
+            self.settings.lives,
+            self.clock.tick,
+        );
+        ship.id = self.next_entity_id;
+        self.next_entity_id += 1;
+        self.ships.insert(id, ship);
+
+        Some(player_num)
+    }
+
+    /// Where the ship of `player_num` waits for the match. Ships are spread
+    /// evenly across rows centered on the field.
+    pub fn spawn_pos(&self, player_num: u8) -> Position {
+        let players = self.settings.max_players as usize;
+        let rows = players.div_ceil(SPAWN_ROW_LEN);
+        let row_len = players.div_ceil(rows);
+
+        let (row, col) = (player_num as usize / row_len, player_num as usize % row_len);
+        let bounds = self.bounds();
+        let gap = bounds.width / (row_len as f32 + 1.0);
+        Position::new(
+            gap * (col as f32 + 1.0),
+            bounds.center().y + SPAWN_ROW_GAP * (row as f32 - (rows - 1) as f32 / 2.0),
+        )
+    }
+
+    pub fn remove_player(&mut self, id: usize) {
+        if let Some(ship) = self.ships.remove(&id) {
+            self.player_num_pool.push_back(ship.player_num);
+        }
+    }
+
+    /// Fires the bullet the player fires in the coming step `ticks` ago, as
+    /// they saw the world, so that lag does not make them miss. The bullet is
+    /// moved on to now and hits ships where they were at each tick on the
+    /// way. Bounded by `Settings::max_rewind`, and forgotten after the step.
+    pub fn rewind(&mut self, id: usize, ticks: u64) {
+        self.rewinds.insert(id, ticks);
+    }
+
+    /// Advances the world by one tick.
+    ///
+    /// Players without an entry in `inputs` are treated as pressing nothing.
+    pub fn step(&mut self, inputs: &HashMap<usize, KeyState>) -> Vec<Event> {
+        let mut events = Vec::new();
+        if self.is_finished {
+            return events;
+        }
+
+        self.clock.tick += 1;
+        let clock = self.clock;
+        let settings = self.settings;
+        let bounds = settings.bounds();
+        // Entities with this id or later came out this tick
+        let first_new_id = self.next_entity_id;
+
+        let mut rewound = Vec::new();
+        let mut all_ready = self.ships.len() > 1;
+        for (player_id, ship) in self.ships.iter_mut() {
+            all_ready &= ship.is_ready;
+
+            let key_state = inputs.get(player_id).copied().unwrap_or_default();
+            if self.is_playing {
+                // Moves ships
+                ship.process_one_tick(&key_state, &clock, &settings, &mut self.rng);
+
+                // Generates a bullet from the ship
+                if key_state.space {
+                    if let Some(mut bullet) = ship.fire(&clock, &settings) {
+                        bullet.id = self.next_entity_id;
+                        self.next_entity_id += 1;
+                        events.push(Event::Fired { player_num: ship.player_num });
+
+                        match self.rewinds.get(player_id) {
+                            Some(ticks) if *ticks > 0 => rewound.push((bullet, *ticks)),
+                            _ => self.bullets.push(bullet),
+                        }
+                    }
+                }
+            } else if key_state.space {
+                // Makes a ship ready
+                ship.is_ready = true;
+            }
+        }
+
+        // If all ships are ready and the game is not being played
+        // all_ready_tick = now
+        if all_ready && !self.is_playing && self.all_ready_tick.is_none() {
+            self.all_ready_tick = Some(clock.tick);
+        }
+
+        // ... after 1 second, starts the game
+        if let Some(all_ready_tick) = self.all_ready_tick {
+            if clock.since(all_ready_tick) > clock.ticks(START_DELAY) {
+                self.is_playing = true;
+                self.all_ready_tick = None;
+
+                for ship in self.ships.values_mut() {
+                    ship.put_on_random_place(&mut self.rng, &bounds);
+                }
+                events.push(Event::Started);
+            }
+        }
+
+        // Catches rewound bullets up with now, against the past positions
+        let max_ticks = clock.ticks(settings.max_rewind()).min(self.history.len() as u64);
+        for (mut bullet, ticks) in rewound {
+            let past = self.history.len() - ticks.min(max_ticks) as usize;
+            let mut is_alive = true;
+
+            for positions in self.history.range(past..) {
+                is_alive = bullet.is_alive(&bounds);
+                if !is_alive {
+                    break;
+                }
+                bullet.move_by_one_tick();
+
+                // The bullet left from where its ship is now, only the others
+                // are rewound
+                for (player_id, pos) in positions {
+                    if let Some(ship) = self.ships.get_mut(player_id).filter(|ship| ship.id != bullet.owner_id) {
+                        if ship.collision_process_at(*pos, &bullet, &clock, &bounds) {
+                            events.push(Event::Hit { player_num: ship.player_num, by: bullet.owner });
+                        }
+                    }
+                }
+            }
+
+            if is_alive {
+                self.bullets.push(bullet);
+            }
+        }
+        self.rewinds.clear();
+
+        // Moves bullets and removes dead bullets
+        self.bullets.retain(|b| b.is_alive(&bounds));
+        for bullet in self.bullets.iter_mut() {
+            bullet.move_by_one_tick();
+        }
+
+        // Ships running into each other are both destroyed. Every contact
+        // counts, even when a ship touched several at once.
+        if self.is_playing {
+            let ships: Vec<_> = self.ships.iter().collect();
+            // (PlayerID, PlayerNum, PlayerNum of the other ship)
+            let mut crashes = Vec::new();
+            for (i, (player_id, ship)) in ships.iter().enumerate() {
+                for (other_id, other) in &ships[i + 1..] {
+                    if ship.touches(other, &bounds) {
+                        crashes.push((**player_id, ship.player_num, other.player_num));
+                        crashes.push((**other_id, other.player_num, ship.player_num));
+                    }
+                }
+            }
+
+            for (player_id, player_num, with) in crashes {
+                if self.ships.get_mut(&player_id).is_some_and(|ship| ship.destroy(&clock)) {
+                    events.push(Event::Crashed { player_num, with });
+                }
+            }
+        }
+
+        self.grid.clear();
+        for (i, bullet) in self.bullets.iter().enumerate() {
+            self.grid.insert(i, bullet.pos - bullet.speed(), bullet.speed(), 0.0);
+        }
+
+        // Only bullets sharing a cell with a ship can hit it. They are tested
+        // in the order they were fired, as with every bullet. Bullets fired
+        // this tick left after the ships had moved, so they only meet the
+        // ships where they are now.
+        let mut nearby = Vec::new();
+        let mut dead_players_id = Vec::new();
+        for (player_id, ship) in self.ships.iter_mut() {
+            let motion = ship.last_move();
+            self.grid.query(ship.pos() - motion, motion, HIT_AREA_RADIUS, &mut nearby);
+
+            for bullet in nearby.iter().map(|&i| &self.bullets[i]) {
+                let is_hit = if bullet.id >= first_new_id {
+                    ship.collision_process_at(ship.pos(), bullet, &clock, &bounds)
+                } else {
+                    ship.collision_process(bullet, &clock, &bounds)
+                };
+                if is_hit {
+                    events.push(Event::Hit { player_num: ship.player_num, by: bullet.owner });
+                }
+            }
+
+            if ship.is_game_over() {
+                dead_players_id.push((*player_id, ship.player_num));
+            }
+        }
+
+        if self.is_playing {
+            let max_ticks = clock.ticks(settings.max_rewind()) as usize;
+            if max_ticks > 0 {
+                self.history.push_back(self.ships.iter().map(|(id, ship)| (*id, ship.pos())).collect());
+                if self.history.len() > max_ticks {
+                    self.history.pop_front();
+                }
+            }
+        }
+
+        // Delete dead ships
+        for (player_id, player_num) in dead_players_id {
+            self.ships.remove(&player_id);
+            self.ranking.push(player_num);
+            events.push(Event::Eliminated { player_id, player_num });
+        }
+
+        // If the number of players is 1, game is over
+        if self.ships.len() <= 1 && self.is_playing {
+            self.ranking.extend(self.ships.values().map(|ship| ship.player_num));
+            self.is_finished = true;
+
+            events.push(Event::Finished {
+                ranking: self.ranking.iter().rev().copied().collect(),
+            });
+        }
+
+        events
+    }
+
+    pub fn ships(&self) -> impl Iterator<Item = (&usize, &Ship)> {
+        self.ships.iter()
+    }
+
+    pub fn ship(&self, id: usize) -> Option<&Ship> {
+        self.ships.get(&id)
+    }
+
+    pub fn bullets(&self) -> &[Bullet] {
+        &self.bullets
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        self.settings.bounds()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.is_playing
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.is_finished
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn clock(&self) -> Clock {
+        self.clock
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Puts the ship of the player at `pos` facing `rad`, standing still.
+    #[cfg(test)]
+    pub(crate) fn place(&mut self, player_id: usize, pos: Position, rad: f32) {
+        let ship = self.ships.get_mut(&player_id).unwrap();
+        let (id, player_num) = (ship.id, ship.player_num);
+        *ship = Ship::new(player_num, player_color(player_num), pos, rad, 3, 0);
+        ship.id = id;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use vector::Vec2;
+
+    use crate::{settings::SIZE_RANGE, ship::ROTATE_SPEED};
+
+    use super::*;
+
+    const TICK_RATE: u32 = 60;
+
+    fn press_space() -> KeyState {
+        KeyState { space: true, ..KeyState::new() }
+    }
+
+    /// A match of the players, once it has started.
+    fn playing_world(settings: Settings, player_ids: &[usize]) -> World {
+        let mut world = World::new(1, TICK_RATE, settings);
+        for id in player_ids {
+            world.add_player(*id);
+        }
+
+        let ready: HashMap<_, _> = player_ids.iter().map(|id| (*id, press_space())).collect();
+        while !world.is_playing() {
+            world.step(&ready);
+        }
+        world
+    }
+
+    #[test]
+    fn player_num_test() {
+        let settings = Settings { max_players: 6, ..Default::default() };
+        let mut world = World::new(0, TICK_RATE, settings);
+
+        for id in 0..6 {
+            assert_eq!(world.add_player(id), Some(id as u8));
+        }
+        assert_eq!(world.add_player(6), None);
+
+        world.remove_player(1);
+        assert_eq!(world.add_player(6), Some(1));
+    }
+
+    #[test]
+    fn player_color_test() {
+        let colors: HashSet<_> = (0..MAX_PLAYERS as u8).map(player_color).collect();
+        assert_eq!(colors.len(), MAX_PLAYERS);
+        assert_eq!(player_color(0), COLOR_LIST[0]);
+    }
+
+    #[test]
+    fn spawn_pos_test() {
+        let (min, max) = (*SIZE_RANGE.start(), *SIZE_RANGE.end());
+        let sizes = [(600, 600), (min, min), (max, min)];
+        for (max_players, (width, height)) in [2, 8, 9, MAX_PLAYERS as u8].into_iter()
+            .flat_map(|max_players| sizes.map(|size| (max_players, size)))
+        {
+            let settings = Settings { max_players, width, height, ..Default::default() };
+            let world = World::new(0, TICK_RATE, settings);
+            let positions: Vec<_> = (0..max_players).map(|num| world.spawn_pos(num)).collect();
+
+            for (i, a) in positions.iter().enumerate() {
+                assert!((0.0..width as f32).contains(&a.x) && (0.0..height as f32).contains(&a.y));
+                for b in &positions[i + 1..] {
+                    assert!(Position::dist2(*a, *b) > 50.0 * 50.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn start_test() {
+        let mut world = World::new(0, TICK_RATE, Settings::default());
+        world.add_player(10);
+        world.add_player(20);
+
+        let inputs = HashMap::from([(10, press_space()), (20, press_space())]);
+        let mut started = false;
+        for _ in 0..120 {
+            started |= world.step(&inputs).contains(&Event::Started);
+        }
+
+        assert!(started);
+        assert!(world.is_playing());
+        assert_eq!(world.add_player(30), None);
+    }
+
+    #[test]
+    fn not_started_alone_test() {
+        let mut world = World::new(0, TICK_RATE, Settings::default());
+        world.add_player(10);
+
+        let inputs = HashMap::from([(10, press_space())]);
+        for _ in 0..120 {
+            assert!(world.step(&inputs).is_empty());
+        }
+
+        assert!(!world.is_playing());
+    }
+
+    #[test]
+    fn entity_id_test() {
+        let mut world = World::new(0, TICK_RATE, Settings::default());
+        world.add_player(10);
+        world.add_player(20);
+        world.remove_player(20);
+        world.add_player(30);
+
+        let inputs = HashMap::from([(10, press_space()), (30, press_space())]);
+        let mut bullet_ids = HashSet::new();
+        for _ in 0..300 {
+            world.step(&inputs);
+            bullet_ids.extend(world.bullets().iter().map(|bullet| bullet.id));
+        }
+
+        // The ship of the player who left keeps its id to itself
+        let ship_ids: Vec<_> = world.ships().map(|(_, ship)| ship.id).collect();
+        assert_eq!(ship_ids, vec![0, 2]);
+        assert!(bullet_ids.len() > 2 && bullet_ids.iter().all(|id| *id > 2));
+
+        for bullet in world.bullets() {
+            let owner = world.ships().find(|(_, ship)| ship.player_num == bullet.owner).unwrap().1;
+            assert_eq!(bullet.owner_id, owner.id);
+        }
+    }
+
+    #[test]
+    fn swept_collision_test() {
+        let clock = Clock::new(TICK_RATE);
+        let bounds = Bounds::default();
+        let shot = |x: f32, speed: f32| {
+            let mut bullet = Bullet::new(1, player_color(1), Position::new(x, 300.0), 0.0, speed);
+            bullet.move_by_one_tick();
+            bullet
+        };
+
+        // Ends 30 px past the ship, having gone through it
+        let mut ship = Ship::new(0, player_color(0), Position::new(300.0, 300.0), 0.0, 3, 0);
+        assert!(ship.collision_process(&shot(270.0, 60.0), &clock, &bounds));
+
+        // Goes off the right edge 15 px short of a ship just over the seam
+        let mut ship = Ship::new(0, player_color(0), Position::new(-5.0, 300.0), 0.0, 3, 0);
+        assert!(ship.collision_process(&shot(600.0, 14.0), &clock, &bounds));
+        let mut ship = Ship::new(0, player_color(0), Position::new(20.0, 300.0), 0.0, 3, 0);
+        assert!(!ship.collision_process(&shot(600.0, 14.0), &clock, &bounds));
+    }
+
+    #[test]
+    fn fire_backwards_test() {
+        // Flies on, turns around and fires against the way it drifts, clear
+        // of the other ship
+        let mut world = playing_world(Settings::default(), &[10, 20]);
+        world.place(10, Position::new(300.0, 300.0), 0.0);
+        world.place(20, Position::new(300.0, 100.0), 0.0);
+
+        let up = KeyState { up: true, ..KeyState::new() };
+        for _ in 0..TICK_RATE * 6 {
+            world.step(&HashMap::from([(10, up)]));
+        }
+        let left = KeyState { left: true, ..KeyState::new() };
+        let turn = (PI / (ROTATE_SPEED / TICK_RATE as f32)).round() as u32;
+        for _ in 0..turn {
+            world.step(&HashMap::from([(10, left)]));
+        }
+
+        let ship = world.ship(10).unwrap();
+        assert!(ship.speed().length() > 3.0 && ship.speed().dot(Vec2::from_angle(ship.rad())) < 0.0);
+
+        let events = world.step(&HashMap::from([(10, press_space())]));
+        assert!(events.contains(&Event::Fired { player_num: 0 }));
+        assert!(!events.iter().any(|event| matches!(event, Event::Hit { .. })));
+        assert!(world.ship(10).unwrap().is_alive());
+    }
+
+    #[test]
+    fn crash_test() {
+        let mut world = playing_world(Settings::default(), &[10, 20, 30]);
+
+        // Two fly head-on into each other, the third keeps away
+        world.place(10, Position::new(200.0, 300.0), 0.0);
+        world.place(20, Position::new(400.0, 300.0), PI);
+        world.place(30, Position::new(100.0, 100.0), 0.0);
+
+        let up = KeyState { up: true, ..KeyState::new() };
+        let inputs = HashMap::from([(10, up), (20, up)]);
+        let mut events = Vec::new();
+        for _ in 0..TICK_RATE * 5 {
+            events = world.step(&inputs);
+            if !events.is_empty() {
+                break;
+            }
+        }
+
+        assert_eq!(events, [
+            Event::Crashed { player_num: 0, with: 1 },
+            Event::Crashed { player_num: 1, with: 0 },
+        ]);
+        let gap = world.bounds().wrapped_dist2(world.ship(10).unwrap().pos(), world.ship(20).unwrap().pos());
+        assert!(gap <= (HIT_AREA_RADIUS * 2.0).powi(2));
+        assert!(!world.ship(10).unwrap().is_alive() && !world.ship(20).unwrap().is_alive());
+        assert!(world.ship(30).unwrap().is_alive());
+    }
+
+    #[test]
+    fn rewind_test() {
+        // Where the bullet of player 10 is after the tick it was fired with
+        // the rewind
+        let fire = |rewind: u64| {
+            let settings = Settings { max_rewind: 250, ..Default::default() };
+            let mut world = World::new(1, TICK_RATE, settings);
+            world.add_player(10);
+            world.add_player(20);
+
+            let ready = HashMap::from([(10, press_space()), (20, press_space())]);
+            while !world.is_playing() {
+                world.step(&ready);
+            }
+            for _ in 0..60 {
+                world.step(&HashMap::new());
+            }
+
+            world.rewind(10, rewind);
+            world.step(&HashMap::from([(10, press_space())]));
+            world.bullets()[0].clone()
+        };
+
+        let (now, rewound, clamped) = (fire(0), fire(5), fire(1000));
+        let speed = now.speed();
+        let moved = |bullet: &Bullet| ((bullet.pos.x - now.pos.x) / speed.x).round();
+        assert_eq!(moved(&rewound), 5.0);
+        // 250 ms at 60 Hz
+        assert_eq!(moved(&clamped), 15.0);
+    }
+
+    #[test]
+    fn rewind_hit_test() {
+        let settings = Settings { max_rewind: 250, ..Default::default() };
+        let mut world = playing_world(settings, &[10, 20]);
+
+        // The shooter drifted back from right in front of its gun, and the
+        // target has since flown away from the way of the bullet
+        world.place(10, Position::new(300.0, 300.0), 0.0);
+        world.place(20, Position::new(400.0, 100.0), 0.0);
+        world.history = (0..5)
+            .map(|_| vec![(10, Position::new(320.0, 300.0)), (20, Position::new(340.0, 300.0))])
+            .collect();
+
+        world.rewind(10, 5);
+        let events = world.step(&HashMap::from([(10, press_space())]));
+
+        assert!(events.contains(&Event::Hit { player_num: 1, by: 0 }));
+        assert!(!events.contains(&Event::Hit { player_num: 0, by: 0 }));
+        assert!(world.ship(10).unwrap().is_alive() && !world.ship(20).unwrap().is_alive());
+    }
+
+    fn play(seed: u64) -> (Vec<Event>, Vec<Ship>, Vec<Bullet>) {
+        let settings = Settings::default();
+        let mut world = World::new(seed, TICK_RATE, settings);
+        let players = settings.max_players as usize;
+        for id in 0..players {
+            world.add_player(id);
+        }
+
+        let mut events = Vec::new();
+        for tick in 0..1200_usize {
+            let inputs = (0..players)
+                .map(|id| (id, KeyState {
+                    left: (tick / 40 + id) % 3 == 0,
+                    right: (tick / 25 + id) % 4 == 0,
+                    up: (tick / 30 + id) % 2 == 0,
+                    space: true,
+                }))
+                .collect();
+            events.extend(world.step(&inputs));
+        }
+
+        let ships = world.ships().map(|(_, ship)| ship.clone()).collect();
+        (events, ships, world.bullets().to_vec())
+    }
+
+    #[test]
+    fn deterministic_test() {
+        assert_eq!(play(42), play(42));
+        assert_ne!(play(42).1, play(43).1);
+    }
+}
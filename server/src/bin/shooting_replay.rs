@@ -0,0 +1,169 @@
+use std::{
+    env, fs::{self, File}, io::{self, BufReader}, path::{Path, PathBuf}, process::ExitCode,
+    time::Duration,
+};
+
+use dotenv::dotenv;
+use game::{format_color, replay::Summary, Replay};
+
+const USAGE: &str = "\
+Usage: shooting_replay <command> [args]
+
+Commands:
+  list [DIR]          Lists the replays in DIR (default: $REPLAY_DIR or ./replays)
+  validate <FILE>...  Checks that replays can be read and played back
+  summary <FILE>...   Shows the winner, duration, shots and hits of replays
+  verify <FILE>...    Re-simulates replays and compares them with the recorded ranking";
+
+fn load(path: &Path) -> io::Result<Replay> {
+    let mut file = BufReader::new(File::open(path)?);
+    Replay::read_from(&mut file)
+}
+
+fn duration(ticks: u64, tick_rate: u32) -> Duration {
+    Duration::from_secs(ticks) / tick_rate
+}
+
+fn player_name(player_num: u8) -> String {
+    format!("Player{}", player_num + 1)
+}
+
+fn list(dir: PathBuf) -> io::Result<bool> {
+    let mut paths: Vec<_> = fs::read_dir(&dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<_>>()?;
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "replay"));
+    paths.sort();
+
+    for path in paths {
+        let id = path.file_stem().unwrap_or_default().to_string_lossy();
+        match load(&path) {
+            Ok(replay) => {
+                let winner = replay.ranking.first()
+                    .map(|player_num| player_name(*player_num))
+                    .unwrap_or("-".to_string());
+                println!(
+                    "{id}\t{:.1?}\t{} players\twinner {winner}",
+                    duration(replay.end_tick, replay.tick_rate),
+                    replay.roster.len(),
+                );
+            },
+            Err(e) => println!("{id}\tunreadable: {e}"),
+        }
+    }
+
+    Ok(true)
+}
+
+fn validate(path: &Path) -> Result<Replay, String> {
+    let replay = load(path).map_err(|e| e.to_string())?;
+    replay.validate()?;
+    Ok(replay)
+}
+
+fn print_summary(replay: &Replay, summary: &Summary) {
+    println!("  tick rate: {} Hz, seed: {:#018x}", replay.tick_rate, replay.seed);
+    let settings = &replay.settings;
+    println!(
+        "  settings: {} lives, {} players, charge {}ms, respawn {}ms, accel {}, bullet {}, rewind {}ms",
+        settings.lives,
+        settings.max_players,
+        settings.charge_time,
+        settings.respawn_time,
+        settings.acceleration,
+        settings.bullet_speed,
+        settings.max_rewind,
+    );
+    println!("  duration: {:.1?}", duration(summary.ticks, replay.tick_rate));
+    match summary.ranking.first() {
+        Some(winner) => println!("  winner: {}", player_name(*winner)),
+        None => println!("  winner: -"),
+    }
+
+    for player in &replay.roster {
+        let rank = summary.ranking.iter()
+            .position(|player_num| *player_num == player.player_num)
+            .map(|i| format!("#{}", i + 1))
+            .unwrap_or("-".to_string());
+        println!(
+            "  {} ({})\trank {rank}\tshots {}\thits {}",
+            player_name(player.player_num),
+            format_color(player.color),
+            summary.shots.get(&player.player_num).unwrap_or(&0),
+            summary.hits.get(&player.player_num).unwrap_or(&0),
+        );
+    }
+}
+
+/// Runs `check` on every file and reports each result, returning whether all
+/// of them passed.
+fn for_each_file(files: &[String], check: impl Fn(&Path) -> Result<(), String>) -> bool {
+    if files.is_empty() {
+        eprintln!("{USAGE}");
+        return false;
+    }
+
+    let mut ok = true;
+    for file in files {
+        match check(Path::new(file)) {
+            Ok(()) => println!("{file}: OK"),
+            Err(e) => {
+                println!("{file}: {e}");
+                ok = false;
+            },
+        }
+    }
+
+    ok
+}
+
+fn main() -> ExitCode {
+    dotenv().ok();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some((command, rest)) = args.split_first() else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+
+    let ok = match command.as_str() {
+        "list" => {
+            let dir = rest.first().cloned()
+                .or(env::var("REPLAY_DIR").ok())
+                .unwrap_or("./replays".to_string());
+
+            list(dir.into()).unwrap_or_else(|e| {
+                eprintln!("Failed to list replays: {e}");
+                false
+            })
+        },
+        "validate" => for_each_file(rest, |path| validate(path).map(|_| ())),
+        "summary" => for_each_file(rest, |path| {
+            let replay = validate(path)?;
+            print_summary(&replay, &replay.simulate());
+            Ok(())
+        }),
+        "verify" => for_each_file(rest, |path| {
+            let replay = validate(path)?;
+            let summary = replay.simulate();
+
+            if summary.ticks != replay.end_tick {
+                Err(format!("Ended at tick {}, recorded {}", summary.ticks, replay.end_tick))
+            } else if summary.ranking != replay.ranking {
+                Err(format!("Ranking {:?} differs from recorded {:?}", summary.ranking, replay.ranking))
+            } else {
+                Ok(())
+            }
+        }),
+        _ => {
+            eprintln!("{USAGE}");
+            false
+        },
+    };
+
+    if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
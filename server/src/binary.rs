@@ -0,0 +1,182 @@
+//! Compact binary frames for clients which ask for them with `format=binary`.
+//!
+//! Only the frames sent every tick, `objects` and `finish`, are binary. Every
+//! other message stays JSON text, including the `palette` message which is
+//! sent first and maps palette indices to colors.
+//!
+//! # Frame format
+//!
+//! All integers are little-endian.
+//!
+//! | Size         | Field                                           |
+//! |--------------|-------------------------------------------------|
+//! | 1            | Kind, `0` for `objects` and `1` for `finish`    |
+//! | 2            | Number of objects                               |
+//! | ...          | Objects                                         |
+//!
+//! Every object starts with its type (1) and its color, then its position as
+//! x and y (2 + 2), stored as `(v + POS_OFFSET) * POS_SCALE`. Ids are the
+//! lower 32 bits of the ids of ships and bullets.
+//!
+//! | Type       | Rest                                                       |
+//! |------------|------------------------------------------------------------|
+//! | `0` ship   | Id (4), player number (1), angle in 1/256 turns (1),       |
+//! |            | flags (1): alive, accelerating                             |
+//! | `1` bullet | Id (4), id of the ship which fired it (4)                  |
+//! | `2` text   | Length (1) and UTF-8 bytes of the text                     |
+//!
+//! A color is its index in the palette (1), or `0xff` followed by RGB (3) if
+//! it is not in the palette.
+
+use std::f32::consts::PI;
+
+use game::{parse_color, player_color, MAX_PLAYERS};
+use serde::Deserialize;
+use vector::Position;
+
+use crate::server::{GameObject, Message};
+
+const OBJECTS: u8 = 0;
+const FINISH: u8 = 1;
+
+const SHIP: u8 = 0;
+const BULLET: u8 = 1;
+const TEXT: u8 = 2;
+
+const ALIVE: u8 = 1 << 0;
+const ACCELERATING: u8 = 1 << 1;
+
+const RGB: u8 = 0xff;
+
+// Positions are in [-15, 975] on the largest field, so 1/64 px fits in 16 bits
+pub const POS_OFFSET: f32 = 16.0;
+pub const POS_SCALE: f32 = 64.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Format {
+    #[default]
+    Json,
+    Binary,
+}
+
+/// Colors binary frames refer to by index, the colors of the players.
+pub fn palette() -> Vec<String> {
+    (0..MAX_PLAYERS as u8).map(player_color).collect()
+}
+
+/// The binary frame of `msg`, or `None` if it is sent as JSON.
+pub fn encode(msg: &Message) -> Option<Vec<u8>> {
+    let (kind, objects) = match msg {
+        Message::Objects { data, .. } => (OBJECTS, data),
+        Message::Finish { data } => (FINISH, data),
+        _ => return None,
+    };
+
+    let palette = palette();
+    let mut buf = vec![kind];
+    buf.extend_from_slice(&(objects.len() as u16).to_le_bytes());
+
+    for object in objects {
+        match object {
+            GameObject::Ship { data } => {
+                buf.push(SHIP);
+                write_color(&mut buf, &palette, data.color());
+                write_pos(&mut buf, data.pos());
+                buf.extend_from_slice(&(data.id as u32).to_le_bytes());
+                buf.push(data.player_num);
+
+                let turns = data.rad().rem_euclid(PI * 2.0) / (PI * 2.0);
+                buf.push((turns * 256.0).round() as u32 as u8);
+
+                let mut flags = 0;
+                if data.is_alive() {
+                    flags |= ALIVE;
+                }
+                if data.is_accelerating() {
+                    flags |= ACCELERATING;
+                }
+                buf.push(flags);
+            },
+            GameObject::Bullet { data } => {
+                buf.push(BULLET);
+                write_color(&mut buf, &palette, data.color());
+                write_pos(&mut buf, data.pos);
+                buf.extend_from_slice(&(data.id as u32).to_le_bytes());
+                buf.extend_from_slice(&(data.owner_id as u32).to_le_bytes());
+            },
+            GameObject::Text { data } => {
+                buf.push(TEXT);
+                write_color(&mut buf, &palette, &data.color);
+                write_pos(&mut buf, data.pos);
+
+                let mut len = data.text.len().min(u8::MAX as usize);
+                while !data.text.is_char_boundary(len) {
+                    len -= 1;
+                }
+                buf.push(len as u8);
+                buf.extend_from_slice(&data.text.as_bytes()[..len]);
+            },
+        }
+    }
+
+    Some(buf)
+}
+
+fn write_color(buf: &mut Vec<u8>, palette: &[String], color: &str) {
+    match palette.iter().position(|c| c == color) {
+        Some(index) => buf.push(index as u8),
+        None => {
+            buf.push(RGB);
+            buf.extend_from_slice(&parse_color(color).unwrap_or_default());
+        },
+    }
+}
+
+fn write_pos(buf: &mut Vec<u8>, pos: Position) {
+    for v in [pos.x, pos.y] {
+        let q = ((v + POS_OFFSET) * POS_SCALE).round().clamp(0.0, u16::MAX as f32) as u16;
+        buf.extend_from_slice(&q.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game::{Bullet, Ship};
+
+    use crate::server::Text;
+
+    use super::*;
+
+    #[test]
+    fn encode_test() {
+        let mut ship = Ship::new(1, player_color(1), Position::new(100.0, 200.5), PI, 3, 0);
+        ship.id = 3;
+        let mut bullet = Bullet::new(1, "#123456".to_string(), Position::new(-15.0, 0.0), 0.0, 7.0);
+        (bullet.id, bullet.owner_id) = (4, 3);
+        let text = Text::new(player_color(0), Position::new(300.0, 100.0), "YOU".to_string());
+        let msg = Message::Objects {
+            data: vec![
+                GameObject::Ship { data: ship },
+                GameObject::Bullet { data: bullet },
+                GameObject::Text { data: text },
+            ],
+            tick: 0,
+            input_seq: None,
+        };
+
+        let buf = encode(&msg).unwrap();
+        assert_eq!(&buf[..3], &[OBJECTS, 3, 0]);
+        assert_eq!(&buf[3..5], &[SHIP, 1]);
+        assert_eq!(u16::from_le_bytes([buf[5], buf[6]]), 116 * 64);
+        assert_eq!(u16::from_le_bytes([buf[7], buf[8]]), 216 * 64 + 32);
+        assert_eq!(&buf[9..16], &[3, 0, 0, 0, 1, 128, ALIVE]);
+        assert_eq!(&buf[16..21], &[BULLET, RGB, 0x12, 0x34, 0x56]);
+        assert_eq!(u16::from_le_bytes([buf[21], buf[22]]), 64);
+        assert_eq!(&buf[25..33], &[4, 0, 0, 0, 3, 0, 0, 0]);
+        assert_eq!(&buf[33..35], &[TEXT, 0]);
+        assert_eq!(&buf[39..], b"\x03YOU");
+
+        assert!(encode(&Message::Ping { time: 0 }).is_none());
+    }
+}
@@ -0,0 +1,188 @@
+//! Delta compression of the frames of a session, for clients which ask for it
+//! with `delta=true`.
+//!
+//! Every frame becomes a numbered snapshot of the ships and bullets, keyed by
+//! [`ObjectKey`]. Once the client acknowledges a snapshot, later frames only
+//! carry what changed since that snapshot. If the acknowledged snapshot is no
+//! longer kept, the full snapshot is sent again.
+
+use std::collections::{HashMap, VecDeque};
+
+use serde::{Serialize, Serializer};
+
+use crate::server::{GameObject, Message};
+
+// Snapshots kept for clients to acknowledge, about 2 seconds at 60 Hz
+const MAX_HISTORY: usize = 128;
+
+/// Serialized as `s<ship id>` for ships and `b<bullet id>` for bullets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ObjectKey {
+    Ship(u64),
+    Bullet(u64),
+}
+
+impl Serialize for ObjectKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ObjectKey::Ship(id) => serializer.collect_str(&format_args!("s{id}")),
+            ObjectKey::Bullet(id) => serializer.collect_str(&format_args!("b{id}")),
+        }
+    }
+}
+
+/// An object with its key, serialized as the object with a `key` field.
+#[derive(Debug, Clone, Serialize)]
+pub struct Entity {
+    pub key: ObjectKey,
+    #[serde(flatten)]
+    pub object: GameObject,
+}
+
+#[derive(Debug, Default)]
+pub struct Snapshots {
+    seq: u64,
+    // (Seq, Objects), oldest first
+    history: VecDeque<(u64, HashMap<ObjectKey, GameObject>)>,
+    acked: Option<u64>,
+}
+
+impl Snapshots {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Turns the objects of a frame into the next snapshot, as a delta
+    /// against the acknowledged one if it is still kept.
+    pub fn encode(&mut self, objects: Vec<GameObject>, tick: u64, input_seq: Option<u64>) -> Message {
+        self.seq += 1;
+
+        let mut texts = Vec::new();
+        let mut current = HashMap::new();
+        for object in objects {
+            match key_of(&object) {
+                Some(key) => {
+                    current.insert(key, object);
+                },
+                None => texts.push(object),
+            }
+        }
+
+        let base = self.acked
+            .and_then(|acked| self.history.iter().find(|(seq, _)| *seq == acked));
+        let msg = match base {
+            Some((base, previous)) => {
+                let updated = current.iter()
+                    .filter(|(key, object)| previous.get(*key) != Some(*object))
+                    .map(|(key, object)| Entity { key: *key, object: object.clone() })
+                    .collect();
+                let removed = previous.keys()
+                    .filter(|key| !current.contains_key(*key))
+                    .copied()
+                    .collect();
+
+                Message::Delta { seq: self.seq, base: *base, tick, input_seq, updated, removed, texts }
+            },
+            None => {
+                let objects = current.iter()
+                    .map(|(key, object)| Entity { key: *key, object: object.clone() })
+                    .collect();
+
+                Message::Snapshot { seq: self.seq, tick, input_seq, objects, texts }
+            },
+        };
+
+        self.history.push_back((self.seq, current));
+        if self.history.len() > MAX_HISTORY {
+            self.history.pop_front();
+        }
+
+        msg
+    }
+
+    /// Records that the client has the snapshot `seq`. Older acknowledgements
+    /// and unknown snapshots are ignored.
+    pub fn ack(&mut self, seq: u64) {
+        if self.acked.is_some_and(|acked| acked >= seq) || seq > self.seq {
+            return;
+        }
+
+        self.acked = Some(seq);
+        // Deltas are never made against older snapshots again
+        self.history.retain(|(kept, _)| *kept >= seq);
+    }
+}
+
+fn key_of(object: &GameObject) -> Option<ObjectKey> {
+    match object {
+        GameObject::Ship { data } => Some(ObjectKey::Ship(data.id)),
+        GameObject::Bullet { data } => Some(ObjectKey::Bullet(data.id)),
+        GameObject::Text { .. } => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game::{player_color, Bullet, Ship};
+    use vector::Position;
+
+    use super::*;
+
+    fn bullet(id: u64, x: f32) -> GameObject {
+        let mut bullet = Bullet::new(0, player_color(0), Position::new(x, 0.0), 0.0, 7.0);
+        bullet.id = id;
+        GameObject::Bullet { data: bullet }
+    }
+
+    #[test]
+    fn delta_test() {
+        let ship = GameObject::Ship {
+            data: Ship::new(0, player_color(0), Position::new(100.0, 100.0), 0.0, 3, 0),
+        };
+        let mut snapshots = Snapshots::new();
+
+        let Message::Snapshot { seq: 1, objects, .. } = snapshots.encode(vec![ship.clone(), bullet(0, 10.0)], 0, None) else {
+            panic!("The first frame must be a snapshot");
+        };
+        assert_eq!(objects.len(), 2);
+
+        let json = serde_json::to_value(&objects[0]).unwrap();
+        assert!(json["key"] == "s0" || json["key"] == "b0");
+        assert!(json["data"].is_object());
+
+        // Not acknowledged yet
+        assert!(matches!(snapshots.encode(vec![ship.clone()], 0, None), Message::Snapshot { seq: 2, .. }));
+
+        snapshots.ack(1);
+        let Message::Delta { seq: 3, base: 1, updated, removed, .. } =
+            snapshots.encode(vec![ship.clone(), bullet(0, 20.0), bullet(1, 0.0)], 0, None)
+        else {
+            panic!("Frames after an ack must be deltas");
+        };
+        let mut keys: Vec<_> = updated.iter().map(|entity| entity.key).collect();
+        keys.sort_by_key(|key| format!("{key:?}"));
+        assert_eq!(keys, vec![ObjectKey::Bullet(0), ObjectKey::Bullet(1)]);
+        assert!(removed.is_empty());
+
+        snapshots.ack(3);
+        let Message::Delta { updated, removed, .. } = snapshots.encode(vec![ship], 0, None) else {
+            panic!("Frames after an ack must be deltas");
+        };
+        assert!(updated.is_empty());
+        assert_eq!(removed.len(), 2);
+    }
+
+    #[test]
+    fn lost_ack_test() {
+        let mut snapshots = Snapshots::new();
+        snapshots.encode(Vec::new(), 0, None);
+        snapshots.ack(1);
+
+        for _ in 0..MAX_HISTORY {
+            snapshots.encode(Vec::new(), 0, None);
+        }
+
+        // The acknowledged snapshot is gone
+        assert!(matches!(snapshots.encode(Vec::new(), 0, None), Message::Snapshot { .. }));
+    }
+}
@@ -3,30 +3,112 @@ use std::{env, fs, time::Instant};
 use actix::*;
 use actix_files::NamedFile;
 use actix_web::{
-    middleware::Logger, get, post, web, App, Error, HttpRequest, HttpResponse, HttpServer
+    delete, error::{ErrorBadRequest, ErrorInternalServerError, ErrorUnauthorized}, get, http::header, middleware::Logger, post, web, App,
+    Error, HttpRequest, HttpResponse, HttpServer
 };
 use actix_web_actors::ws;
-use serde::Deserialize;
+use game::{Difficulty, Settings};
+use serde::{de::DeserializeOwned, Deserialize};
 use dotenv::dotenv;
 
 mod server;
-mod ship;
-mod keystate;
-mod bullet;
 mod session;
+mod room;
+mod replay;
+mod binary;
+mod delta;
+
+const LOBBY_REPLAYS: usize = 10;
+// Passwords are kept out of URLs, which end up in logs
+const PASSWORD_HEADER: &str = "X-Room-Password";
 
 #[derive(Debug, Deserialize)]
 pub struct RoomRequest {
     id: usize,
     watch: bool,
+    // Invite code of a private room
+    code: Option<String>,
+    // Connects an external bot, which needs a bot token
+    #[serde(default)]
+    bot: bool,
+    #[serde(default)]
+    format: binary::Format,
+    // Sends deltas against acknowledged snapshots instead of whole frames
+    #[serde(default)]
+    delta: bool,
+}
+
+/// Tokens external bots authenticate with, from `BOT_TOKENS`.
+#[derive(Debug, Clone, Default)]
+pub struct BotTokens(Vec<String>);
+
+impl BotTokens {
+    fn from_env() -> Self {
+        let tokens = env::var("BOT_TOKENS").unwrap_or_default();
+        Self(tokens.split(',').map(str::trim).filter(|token| !token.is_empty()).map(str::to_string).collect())
+    }
+
+    /// Whether the request carries `Authorization: Bearer <token>` with a known token.
+    fn authorize(&self, req: &HttpRequest) -> bool {
+        req.headers().get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| {
+                // Every token is compared, not only up to the one that matches
+                self.0.iter().fold(false, |found, known| found | server::secret_matches(Some(token), known))
+            })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CodeQuery {
+    code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReplayRequest {
+    id: String,
+    #[serde(default)]
+    format: binary::Format,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AddBotRequest {
+    difficulty: Difficulty,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CreateRoomRequest {
+    settings: Settings,
+    // Hides the room from the list and gives it an invite code
+    private: bool,
+    password: Option<String>,
 }
 
 async fn ws_route(
     req: HttpRequest,
     stream: web::Payload,
     srv: web::Data<Addr<server::GameServer>>,
+    bot_tokens: web::Data<BotTokens>,
     query: web::Query<RoomRequest>,
 ) -> Result<HttpResponse, Error> {
+    if query.bot {
+        if query.watch {
+            return Err(ErrorBadRequest("Bots cannot watch"));
+        }
+        if !bot_tokens.authorize(&req) {
+            return Err(ErrorUnauthorized("Invalid bot token"));
+        }
+    }
+
+    // Private rooms cannot be entered without their code, the password is
+    // checked when the client joins
+    let credentials = server::Credentials { code: query.code.clone(), ..Default::default() };
+    srv.send(server::GetRoom { room_id: query.id, credentials: credentials.clone() }).await
+        .map_err(ErrorInternalServerError)??;
+
     ws::start(
         session::GameSession {
             id: 0,
@@ -34,64 +116,112 @@ async fn ws_route(
             room: query.id,
             addr: srv.get_ref().clone(),
             watch: query.watch,
+            bot: query.bot,
+            format: query.format,
+            snapshots: query.delta.then(delta::Snapshots::new),
+            rtt: None,
+            ping_time: None,
+            credentials,
         },
         &req,
         stream,
     )
 }
 
+async fn replay_ws_route(
+    req: HttpRequest,
+    stream: web::Payload,
+    timing: web::Data<room::Timing>,
+    query: web::Query<ReplayRequest>,
+) -> Result<HttpResponse, Error> {
+    let ReplayRequest { id, format } = query.into_inner();
+    let replay = web::block(move || replay::load(&id)).await?
+        .map_err(actix_web::error::ErrorNotFound)?;
+
+    ws::start(replay::ReplaySession::new(replay, **timing, format), &req, stream)
+}
+
 #[get("/")]
-async fn lobby(_req: HttpRequest, srv: web::Data<Addr<server::GameServer>>) -> HttpResponse {
-    let mut rooms = srv.send(server::ListRooms).await.unwrap();
+async fn lobby(_req: HttpRequest, srv: web::Data<Addr<server::GameServer>>) -> actix_web::Result<HttpResponse> {
+    let rooms = srv.send(server::ListRooms).await.map_err(ErrorInternalServerError)?;
     let mut rooms_html = String::new();
 
-    rooms.sort();
-    for room in rooms {
-        let player_count = srv.send(server::GetPlayerCount {
-            room_id: room,
-        }).await.unwrap();
-        let is_playing = srv.send(server::IsPlaying {
-            room_id: room,
-        }).await.unwrap();
+    for room in rooms.into_iter().filter(|room| room.players > 0) {
+        let is_playing = room.state != room::RoomState::Recruiting;
 
         rooms_html += "<div class=\"room\">";
         rooms_html += "<div class=\"information\">";
-        rooms_html += format!("<p class=\"room-id\">{:04}</p>", room).as_str();
+        rooms_html += format!("<p class=\"room-id\">{:04}</p>", room.id).as_str();
         rooms_html += if is_playing {
             "<p class=\"room-condition playing\">Playing</p>"
         } else {
             "<p class=\"room-condition recruiting\">Recruiting</p>"
         };
-        rooms_html += format!("<p class=\"player-num\">{}/4</p>", player_count).as_str();
+        rooms_html += format!("<p class=\"player-num\">{}/{}</p>", room.players, room.capacity).as_str();
         rooms_html += "</div>";
         rooms_html += "<div class=\"buttons\">";
-        rooms_html += format!("<a href=\"/game?id={}&watch=true\">Watch</a>", room).as_str();
-        if player_count < 4 {
+        rooms_html += format!("<a href=\"/game?id={}&watch=true\">Watch</a>", room.id).as_str();
+        if room.players < room.capacity {
             if is_playing {
                 rooms_html += "<a href=\"#\" class=\"full\">Join</a>"
             } else {
-                rooms_html += format!("<a href=\"/game?id={}&watch=false\">Join</a>", room).as_str();
+                rooms_html += format!("<a href=\"/game?id={}&watch=false\">Join</a>", room.id).as_str();
             }
         } else {
-            rooms_html += "<a href\"#\" class=\"full\">Join</a>";
+            rooms_html += "<a href=\"#\" class=\"full\">Join</a>";
         }
         rooms_html += "</div>";
+        rooms_html += format!(
+            "<p class=\"room-settings\">{}Lives {} / Charge {}ms / Respawn {}ms / Accel {} / Bullet {} / Rewind {}ms / Field {}x{}</p>",
+            if room.locked { "Password required / " } else { "" },
+            room.settings.lives,
+            room.settings.charge_time,
+            room.settings.respawn_time,
+            room.settings.acceleration,
+            room.settings.bullet_speed,
+            room.settings.max_rewind,
+            room.settings.width,
+            room.settings.height,
+        ).as_str();
         rooms_html += "</div>";
     }
 
+    let mut replays_html = String::new();
+    for id in replay::list().unwrap_or_default().into_iter().take(LOBBY_REPLAYS) {
+        replays_html += "<div class=\"room\">";
+        replays_html += "<div class=\"information\">";
+        replays_html += format!("<p class=\"room-id\">{}</p>", id).as_str();
+        replays_html += "</div>";
+        replays_html += "<div class=\"buttons\">";
+        replays_html += format!("<a href=\"/replay?id={}\">Watch</a>", id).as_str();
+        replays_html += "</div>";
+        replays_html += "</div>";
+    }
+
     if let Ok(content) = fs::read_to_string("./static/index.html") {
-        let content = content.replace("{server_replace}", &rooms_html);
+        let content = content.replace("{server_replace}", &rooms_html)
+            .replace("{replays_replace}", &replays_html)
+            .replace("{max_players_replace}", &game::MAX_PLAYERS.to_string());
 
-        HttpResponse::Ok()
+        Ok(HttpResponse::Ok()
             .content_type("text/html; charset=UTF-8")
-            .body(content)
+            .body(content))
     } else {
-        HttpResponse::NotFound().finish()
+        Ok(HttpResponse::NotFound().finish())
     }
 }
 
 #[get("/game")]
 async fn join_game(_req: HttpRequest, _srv: web::Data<Addr<server::GameServer>>) -> actix_web::Result<NamedFile> {
+    game_page()
+}
+
+#[get("/replay")]
+async fn watch_replay(_req: HttpRequest) -> actix_web::Result<NamedFile> {
+    game_page()
+}
+
+fn game_page() -> actix_web::Result<NamedFile> {
     let use_ssl = env::var("USE_SSL").unwrap_or("false".to_string())
         .parse()
         .expect("The value of USE_SSL is invalid");
@@ -109,16 +239,119 @@ async fn static_file(info: web::Path<String>) -> actix_web::Result<NamedFile> {
 }
 
 #[get("/rooms")]
-async fn get_rooms(_req: HttpRequest, srv: web::Data<Addr<server::GameServer>>) -> HttpResponse {
-    let mut rooms = srv.send(server::ListRooms).await.unwrap();
-    rooms.sort();
-    HttpResponse::Ok().body(format!("{rooms:?}"))
+async fn get_rooms(_req: HttpRequest, srv: web::Data<Addr<server::GameServer>>) -> actix_web::Result<HttpResponse> {
+    let rooms = srv.send(server::ListRooms).await.map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(rooms))
+}
+
+#[get("/rooms/{id}")]
+async fn get_room(
+    info: web::Path<usize>,
+    query: web::Query<CodeQuery>,
+    srv: web::Data<Addr<server::GameServer>>,
+) -> actix_web::Result<HttpResponse> {
+    let room = srv.send(server::GetRoom {
+        room_id: info.into_inner(),
+        credentials: server::Credentials { code: query.into_inner().code, ..Default::default() },
+    }).await
+        .map_err(ErrorInternalServerError)??;
+    Ok(HttpResponse::Ok().json(room))
+}
+
+#[delete("/rooms/{id}")]
+async fn delete_room(
+    req: HttpRequest,
+    info: web::Path<usize>,
+    query: web::Query<CodeQuery>,
+    srv: web::Data<Addr<server::GameServer>>,
+) -> actix_web::Result<HttpResponse> {
+    srv.send(server::DeleteRoom {
+        room_id: info.into_inner(),
+        credentials: credentials(&req, query.into_inner())?,
+    }).await
+        .map_err(ErrorInternalServerError)??;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post("/rooms/{id}/bots")]
+async fn add_bot(
+    req: HttpRequest,
+    info: web::Path<usize>,
+    query: web::Query<CodeQuery>,
+    body: web::Bytes,
+    srv: web::Data<Addr<server::GameServer>>,
+) -> actix_web::Result<HttpResponse> {
+    let request: AddBotRequest = json_or_default(&body)?;
+    let room_id = info.into_inner();
+
+    let bot = srv.send(server::AddBot {
+        room_id,
+        credentials: credentials(&req, query.into_inner())?,
+        difficulty: request.difficulty,
+    }).await.map_err(ErrorInternalServerError)??;
+
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("/rooms/{room_id}/bots/{}", bot.id)))
+        .json(bot))
+}
+
+#[delete("/rooms/{id}/bots/{bot_id}")]
+async fn remove_bot(
+    req: HttpRequest,
+    info: web::Path<(usize, usize)>,
+    query: web::Query<CodeQuery>,
+    srv: web::Data<Addr<server::GameServer>>,
+) -> actix_web::Result<HttpResponse> {
+    let (room_id, bot_id) = info.into_inner();
+
+    srv.send(server::RemoveBot {
+        room_id,
+        credentials: credentials(&req, query.into_inner())?,
+        bot_id,
+    }).await.map_err(ErrorInternalServerError)??;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Credentials of a request to change a room.
+fn credentials(req: &HttpRequest, query: CodeQuery) -> actix_web::Result<server::Credentials> {
+    let password = req.headers().get(PASSWORD_HEADER)
+        .map(|value| value.to_str().map(str::to_string))
+        .transpose()
+        .map_err(ErrorBadRequest)?;
+
+    Ok(server::Credentials { code: query.code, password, resume: None })
+}
+
+/// Parses an optional JSON body, an empty one gives the defaults.
+fn json_or_default<T: Default + DeserializeOwned>(body: &[u8]) -> actix_web::Result<T> {
+    if body.is_empty() {
+        Ok(T::default())
+    } else {
+        serde_json::from_slice(body).map_err(ErrorBadRequest)
+    }
+}
+
+#[get("/replays")]
+async fn get_replays(_req: HttpRequest) -> actix_web::Result<HttpResponse> {
+    let replays = web::block(replay::list).await??;
+    Ok(HttpResponse::Ok().json(replays))
 }
 
 #[post("/rooms")]
-async fn create_room(_req: HttpRequest, srv: web::Data<Addr<server::GameServer>>) -> HttpResponse {
-    let room_id = srv.send(server::CreateRoom).await.unwrap();
-    HttpResponse::Created().body(format!("{room_id}"))
+async fn create_room(
+    body: web::Bytes,
+    srv: web::Data<Addr<server::GameServer>>,
+) -> actix_web::Result<HttpResponse> {
+    // Without a body, the room is created with the default settings
+    let request: CreateRoomRequest = json_or_default(&body)?;
+
+    let CreateRoomRequest { settings, private, password } = request;
+    let room = srv.send(server::CreateRoom { settings, private, password }).await
+        .map_err(ErrorInternalServerError)??;
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("/rooms/{}", room.id)))
+        .json(room))
 }
 
 #[actix_web::main]
@@ -131,19 +364,40 @@ async fn main() -> std::io::Result<()> {
         .parse()
         .expect("The value of PORT is invalid");
 
-    let server = server::GameServer::new().start();
+    let tick_rate: u32 = env::var("TICK_RATE").unwrap_or("60".to_string())
+        .parse()
+        .expect("The value of TICK_RATE is invalid");
+    assert!((30..=240).contains(&tick_rate), "TICK_RATE must be between 30 and 240");
+
+    let broadcast_rate: u32 = env::var("BROADCAST_RATE").unwrap_or("60".to_string())
+        .parse()
+        .expect("The value of BROADCAST_RATE is invalid");
+    assert!((1..=tick_rate).contains(&broadcast_rate), "BROADCAST_RATE must be between 1 and TICK_RATE");
+
+    let timing = room::Timing { tick_rate, broadcast_rate };
+    let server = server::GameServer::new(timing).start();
+    let bot_tokens = BotTokens::from_env();
 
     log::info!("Starting shooting server...");
 
     HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(server.clone()))
+            .app_data(web::Data::new(timing))
+            .app_data(web::Data::new(bot_tokens.clone()))
             .route("/ws", web::get().to(ws_route))
+            .route("/ws/replay", web::get().to(replay_ws_route))
             .service(lobby)
             .service(join_game)
+            .service(watch_replay)
             .service(static_file)
             .service(get_rooms)
+            .service(get_room)
             .service(create_room)
+            .service(delete_room)
+            .service(add_bot)
+            .service(remove_bot)
+            .service(get_replays)
             .wrap(Logger::default())
     })
     .bind(("0.0.0.0", port))?
@@ -0,0 +1,202 @@
+use std::{
+    env, fs::{self, File}, io::{self, BufReader, BufWriter, Write}, path::PathBuf,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use actix::prelude::*;
+use actix_web_actors::ws;
+use game::{Event, Playback, Replay};
+
+use crate::{binary::{self, Format}, room, server::Message, session};
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(15);
+const EXTENSION: &str = "replay";
+
+fn replay_dir() -> PathBuf {
+    env::var("REPLAY_DIR").unwrap_or("./replays".to_string()).into()
+}
+
+fn replay_path(id: &str) -> io::Result<PathBuf> {
+    // Replay ids are generated by `save`, anything else may escape the directory
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit() || c == '-') {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "Invalid replay id"));
+    }
+
+    Ok(replay_dir().join(format!("{id}.{EXTENSION}")))
+}
+
+/// Writes the replay to the replay directory and returns its id.
+pub fn save(room_id: usize, replay: &Replay) -> io::Result<String> {
+    fs::create_dir_all(replay_dir())?;
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let id = format!("{timestamp}-{room_id:04}");
+
+    let mut file = BufWriter::new(File::create(replay_path(&id)?)?);
+    replay.write_to(&mut file)?;
+    file.flush()?;
+
+    log::info!("Saved replay {id}");
+
+    Ok(id)
+}
+
+pub fn load(id: &str) -> io::Result<Replay> {
+    let mut file = BufReader::new(File::open(replay_path(id)?)?);
+    Replay::read_from(&mut file)
+}
+
+/// Ids of the stored replays, newest first.
+pub fn list() -> io::Result<Vec<String>> {
+    let entries = match fs::read_dir(replay_dir()) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut ids = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == EXTENSION) {
+            if let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) {
+                ids.push(id.to_string());
+            }
+        }
+    }
+
+    ids.sort_by(|a, b| b.cmp(a));
+    Ok(ids)
+}
+
+/// Streams a stored replay to a client with the same frames spectators of a
+/// live room get.
+#[derive(Debug)]
+pub struct ReplaySession {
+    hb: Instant,
+    playback: Playback,
+    // Sends a frame every this many ticks
+    broadcast_ticks: u64,
+    format: Format,
+    is_done: bool,
+}
+
+impl ReplaySession {
+    pub fn new(replay: Replay, timing: room::Timing, format: Format) -> Self {
+        let broadcast_ticks = (replay.tick_rate / timing.broadcast_rate).max(1) as u64;
+
+        Self {
+            hb: Instant::now(),
+            playback: Playback::new(replay),
+            broadcast_ticks,
+            format,
+            is_done: false,
+        }
+    }
+
+    fn hb(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
+            if Instant::now().duration_since(act.hb) > CLIENT_TIMEOUT {
+                ctx.stop();
+                return;
+            }
+
+            let msg = Message::Ping { time: session::unix_millis() };
+            ctx.text(serde_json::to_string(&msg).unwrap());
+        });
+    }
+
+    fn send(&self, ctx: &mut ws::WebsocketContext<Self>, msg: Message) {
+        if self.format == Format::Binary {
+            if let Some(frame) = binary::encode(&msg) {
+                ctx.binary(frame);
+                return;
+            }
+        }
+
+        ctx.text(serde_json::to_string(&msg).unwrap());
+    }
+
+    fn process_one_tick(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        let Some(events) = self.playback.step() else {
+            // The replay ended without a result
+            self.send(ctx, Message::Finish { data: room::finish_objects(&[], &self.playback.replay().settings.bounds()) });
+            self.is_done = true;
+            return;
+        };
+
+        for event in events {
+            if let Event::Finished { ranking } = event {
+                self.send(ctx, Message::Finish { data: room::finish_objects(&ranking, &self.playback.replay().settings.bounds()) });
+                self.is_done = true;
+                return;
+            }
+        }
+
+        if self.playback.world().clock().tick.is_multiple_of(self.broadcast_ticks) {
+            let world = self.playback.world();
+            let data = room::world_objects(world);
+            self.send(ctx, Message::Objects { data, tick: world.clock().tick, input_seq: None });
+        }
+    }
+}
+
+impl Actor for ReplaySession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.hb(ctx);
+        if self.format == Format::Binary {
+            self.send(ctx, Message::Palette { data: binary::palette() });
+        }
+        self.send(ctx, Message::Settings { data: self.playback.replay().settings });
+
+        // Skips the lobby of the room
+        while !self.playback.world().is_playing() {
+            if self.playback.step().is_none() {
+                break;
+            }
+        }
+
+        let tick_interval = Duration::from_secs(1) / self.playback.replay().tick_rate;
+        ctx.run_interval(tick_interval, |act, ctx| {
+            if !act.is_done {
+                act.process_one_tick(ctx);
+            }
+        });
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ReplaySession {
+    fn handle(&mut self, item: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        let msg = match item {
+            Err(_) => {
+                ctx.stop();
+                return;
+            },
+            Ok(msg) => msg,
+        };
+
+        match msg {
+            ws::Message::Ping(msg) => {
+                self.hb = Instant::now();
+                ctx.pong(&msg);
+            },
+            // Replays take no input, so any message only proves the client is alive
+            ws::Message::Pong(_) | ws::Message::Text(_) => {
+                self.hb = Instant::now();
+            },
+            ws::Message::Binary(_) => log::warn!("Unexpected binary"),
+            ws::Message::Close(reason) => {
+                ctx.close(reason);
+                ctx.stop();
+            },
+            ws::Message::Continuation(_) => {
+                ctx.stop();
+            },
+            ws::Message::Nop => (),
+        }
+    }
+}
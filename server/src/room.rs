@@ -0,0 +1,972 @@
+use std::{collections::{HashMap, HashSet, VecDeque}, time::{Duration, Instant}};
+
+use actix::prelude::*;
+use game::{player_color, Bot, Difficulty, Event, KeyState, Recorder, Settings, World, WorldState};
+use rand::{distr::Alphanumeric, Rng};
+use serde::Serialize;
+use vector::{Bounds, Position};
+
+use crate::{
+    replay,
+    server::{GameObject, Message, PlayerPing, RoomError, Text, MAX_PLAYER_ID},
+    session::saturating_millis,
+};
+
+const MAX_CATCH_UP_TICKS: u32 = 5;
+// Keys for later ticks than this are applied this far ahead
+const MAX_INPUT_LEAD: Duration = Duration::from_secs(1);
+// Keys waiting per player, more are dropped
+const MAX_PENDING_INPUTS: usize = 64;
+// How long the ship of a player who dropped out of a match waits for them
+pub const RESUME_GRACE: Duration = Duration::from_secs(30);
+// Alphanumeric, about 119 bits
+const RESUME_TOKEN_LEN: usize = 20;
+
+/// How often rooms simulate and how often they send snapshots, in Hz.
+#[derive(Debug, Clone, Copy)]
+pub struct Timing {
+    pub tick_rate: u32,
+    pub broadcast_rate: u32,
+}
+
+impl Timing {
+    pub fn tick_interval(&self) -> Duration {
+        Duration::from_secs(1) / self.tick_rate
+    }
+
+    pub fn broadcast_interval(&self) -> Duration {
+        Duration::from_secs(1) / self.broadcast_rate
+    }
+}
+
+/// Joins the room, as the player id given back. Players resuming with a
+/// token get the id they played as.
+#[derive(Message)]
+#[rtype(result = "Result<usize, RoomError>")]
+pub struct Join {
+    pub id: usize,
+    pub addr: Recipient<Message>,
+    pub watch: bool,
+    // An external bot, which gets the world state every tick
+    pub bot: bool,
+    // Resume token of a player who was in the room
+    pub resume: Option<String>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Leave {
+    pub id: usize,
+    // The session leaving, ignored if the player has resumed elsewhere
+    pub addr: Recipient<Message>,
+    pub watch: bool,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct KeyUpdate {
+    pub id: usize,
+    // The session sending, ignored if the player has resumed elsewhere
+    pub addr: Recipient<Message>,
+    pub state: KeyState,
+    // The tick the keys apply at, if the client tells. Keys for past ticks
+    // apply at the next one.
+    pub tick: Option<u64>,
+    // Sequence number of the keys, echoed once they are applied
+    pub seq: Option<u64>,
+}
+
+/// A player who dropped out of a match, whose ship a bot flies until they
+/// resume or the grace period ends.
+#[derive(Debug)]
+struct Dropped {
+    deadline: Instant,
+    bot: Bot,
+}
+
+/// Keys waiting for their tick.
+#[derive(Debug, Clone, Copy)]
+struct Input {
+    tick: u64,
+    seq: Option<u64>,
+    state: KeyState,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<BotInfo, RoomError>")]
+pub struct AddBot {
+    pub difficulty: Difficulty,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), RoomError>")]
+pub struct RemoveBot {
+    pub id: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BotInfo {
+    pub id: usize,
+    pub player_num: u8,
+    pub difficulty: Difficulty,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RoomState {
+    Recruiting,
+    Playing,
+    Finished,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoomInfo {
+    pub id: usize,
+    pub state: RoomState,
+    // Including bots
+    pub players: usize,
+    pub spectators: usize,
+    pub capacity: usize,
+    pub settings: Settings,
+    pub bots: Vec<BotInfo>,
+    // Set by the server, which guards who may enter the room
+    pub private: bool,
+    pub locked: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Latency {
+    pub id: usize,
+    pub rtt: Duration,
+}
+
+#[derive(Message)]
+#[rtype(result = "RoomInfo")]
+pub struct GetInfo;
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Close;
+
+/// A room simulated by its own actor.
+///
+/// Every room owns its world and listeners, and ticks on its own timer, so
+/// a busy room never holds up the others.
+#[derive(Debug)]
+pub struct GameRoom {
+    id: usize,
+    timing: Timing,
+    last_update: Instant,
+    // Simulation time not yet consumed by ticks
+    lag: Duration,
+    // Time since the last snapshot was due
+    broadcast_lag: Duration,
+    world: World,
+    recorder: Recorder,
+    // (PlayerID, PlayerSession)
+    listeners: HashMap<usize, Recipient<Message>>,
+    // (PlayerID, KeyState)
+    keystates: HashMap<usize, KeyState>,
+    // (PlayerID, Bot), bots have no listener
+    bots: HashMap<usize, Bot>,
+    // Listeners which are external bots
+    agents: HashSet<usize>,
+    // (PlayerID, Tick), the newest tick keys were sent for
+    input_ticks: HashMap<usize, u64>,
+    // (PlayerID, Inputs), in order of ticks
+    pending_inputs: HashMap<usize, VecDeque<Input>>,
+    // (PlayerID, Seq), the last keys applied
+    input_seqs: HashMap<usize, u64>,
+    // (PlayerID, RTT) of players with a ship
+    latencies: HashMap<usize, Duration>,
+    // (Token, PlayerID) of players with a ship
+    resume_tokens: HashMap<String, usize>,
+    // (PlayerID, Dropped), players who may still resume
+    dropped: HashMap<usize, Dropped>,
+    player_count: usize,
+    spectator_count: usize,
+}
+
+impl GameRoom {
+    pub fn new(id: usize, timing: Timing, settings: Settings) -> Self {
+        let world = World::new(rand::random(), timing.tick_rate, settings);
+        let recorder = Recorder::new(world.seed(), timing.tick_rate, settings);
+
+        Self {
+            id,
+            timing,
+            last_update: Instant::now(),
+            lag: Duration::ZERO,
+            broadcast_lag: Duration::ZERO,
+            world,
+            recorder,
+            listeners: HashMap::new(),
+            keystates: HashMap::new(),
+            bots: HashMap::new(),
+            agents: HashSet::new(),
+            input_ticks: HashMap::new(),
+            pending_inputs: HashMap::new(),
+            input_seqs: HashMap::new(),
+            latencies: HashMap::new(),
+            resume_tokens: HashMap::new(),
+            dropped: HashMap::new(),
+            player_count: 0,
+            spectator_count: 0,
+        }
+    }
+
+    pub fn info(&self) -> RoomInfo {
+        let state = if self.world.is_finished() {
+            RoomState::Finished
+        } else if self.world.is_playing() {
+            RoomState::Playing
+        } else {
+            RoomState::Recruiting
+        };
+        let settings = *self.world.settings();
+
+        let mut bots: Vec<_> = self.bots.iter()
+            .filter_map(|(id, bot)| {
+                let ship = self.world.ship(*id)?;
+                Some(BotInfo { id: *id, player_num: ship.player_num, difficulty: bot.difficulty() })
+            })
+            .collect();
+        bots.sort_by_key(|bot| bot.player_num);
+
+        RoomInfo {
+            id: self.id,
+            state,
+            players: self.player_count,
+            spectators: self.spectator_count,
+            capacity: settings.max_players as usize,
+            settings,
+            bots,
+            private: false,
+            locked: false,
+            code: None,
+        }
+    }
+
+    fn update(&mut self) {
+        if self.world.is_finished() {
+            return;
+        }
+
+        let now = Instant::now();
+        let expired: Vec<_> = self.dropped.iter()
+            .filter(|(_, dropped)| dropped.deadline <= now)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            log::info!("Room {}: player {id} did not come back", self.id);
+            self.remove_player(id);
+        }
+
+        let elapsed = now.duration_since(self.last_update);
+        self.last_update = now;
+
+        // Catches up with missed ticks, but gives up on time the room can no
+        // longer make up for instead of spiraling
+        let tick_interval = self.timing.tick_interval();
+        let max_lag = tick_interval * MAX_CATCH_UP_TICKS;
+        self.lag += elapsed;
+        if self.lag > max_lag {
+            log::warn!("Room {} is {:?} behind, skipping ticks", self.id, self.lag - max_lag);
+            self.lag = max_lag;
+        }
+
+        while self.lag >= tick_interval {
+            self.lag -= tick_interval;
+
+            self.apply_inputs();
+
+            for (id, bot) in self.bots.iter_mut() {
+                let state = bot.think(*id, &self.world);
+                self.keystates.insert(*id, state);
+                self.recorder.input(self.world.clock().tick, *id, state);
+            }
+            for (id, dropped) in self.dropped.iter_mut() {
+                let state = dropped.bot.think(*id, &self.world);
+                self.keystates.insert(*id, state);
+                self.recorder.input(self.world.clock().tick, *id, state);
+            }
+
+            for event in self.world.step(&self.keystates) {
+                log::debug!("Room {}: {event:?}", self.id);
+                match event {
+                    Event::Eliminated { player_id, .. } => self.eliminated(player_id),
+                    Event::Finished { ranking } => {
+                        self.finish(&ranking);
+                        return;
+                    },
+                    _ => (),
+                }
+            }
+
+            self.send_states();
+        }
+
+        let broadcast_interval = self.timing.broadcast_interval();
+        self.broadcast_lag += elapsed;
+        if self.broadcast_lag >= broadcast_interval {
+            self.broadcast_lag = (self.broadcast_lag - broadcast_interval).min(broadcast_interval);
+            self.broadcast();
+        }
+    }
+
+    /// Applies the buffered keys due by the coming tick. Bullets fired with
+    /// keys which came late are rewound by how late they were, if the room
+    /// compensates lag.
+    fn apply_inputs(&mut self) {
+        let tick = self.world.clock().tick;
+        let compensates = self.world.settings().max_rewind > 0;
+
+        for (id, pending) in self.pending_inputs.iter_mut() {
+            while let Some(input) = pending.front().filter(|input| input.tick <= tick + 1).copied() {
+                pending.pop_front();
+
+                self.keystates.insert(*id, input.state);
+                self.recorder.input(tick, *id, input.state);
+                if let Some(seq) = input.seq {
+                    self.input_seqs.insert(*id, seq);
+                }
+
+                let late = tick + 1 - input.tick;
+                if compensates && input.state.space && late > 0 {
+                    let ticks = late.min(u8::MAX as u64);
+                    self.world.rewind(*id, ticks);
+                    self.recorder.rewind(tick, *id, ticks as u8);
+                }
+            }
+        }
+    }
+
+    /// Sends the current state of the room to every listener.
+    fn broadcast(&self) {
+        let objects = world_objects(&self.world);
+        let bounds = self.world.bounds();
+
+        // Display texts to one player
+        let mut not_broadcast_texts = HashMap::new();
+        if !self.world.is_playing() {
+            for (player_id, ship) in self.world.ships() {
+                not_broadcast_texts.insert(*player_id, vec![
+                    GameObject::Text { data: Text::player_num(ship.player_num, &bounds) },
+                    GameObject::Text { data: Text::you(ship) },
+                    GameObject::Text { data: Text::space_to_ready(ship.player_num, &bounds) }
+                ]);
+            }
+        }
+
+        // Send data of objects to clients
+        for (player_id, session) in &self.listeners {
+            if self.agents.contains(player_id) {
+                continue;
+            }
+
+            let mut data = objects.clone();
+            if let Some(not_broadcast_texts) = not_broadcast_texts.remove(player_id) {
+                data.extend(not_broadcast_texts);
+            }
+
+            session.do_send(Message::Objects {
+                data,
+                tick: self.world.clock().tick,
+                input_seq: self.input_seqs.get(player_id).copied(),
+            });
+        }
+    }
+
+    /// Sends the round-trip times of the players to every listener.
+    fn send_pings(&self) {
+        let mut data: Vec<_> = self.latencies.iter()
+            .filter_map(|(id, rtt)| {
+                let ship = self.world.ship(*id)?;
+                Some(PlayerPing {
+                    player_num: ship.player_num,
+                    color: ship.color().to_string(),
+                    rtt: saturating_millis(*rtt),
+                })
+            })
+            .collect();
+        data.sort_by_key(|ping| ping.player_num);
+
+        for session in self.listeners.values() {
+            session.do_send(Message::Pings { data: data.clone() });
+        }
+    }
+
+    /// Ticks it takes keys of the player to reach the room, half their
+    /// round-trip time.
+    fn one_way_ticks(&self, id: usize) -> u64 {
+        let rtt = self.latencies.get(&id).copied().unwrap_or_default();
+        self.world.clock().ticks(rtt / 2)
+    }
+
+    /// Gives the player a new token to resume with, replacing the old one.
+    fn issue_resume_token(&mut self, id: usize) -> String {
+        self.resume_tokens.retain(|_, player| *player != id);
+
+        let token: String = rand::rng().sample_iter(Alphanumeric)
+            .take(RESUME_TOKEN_LEN)
+            .map(char::from)
+            .collect();
+        self.resume_tokens.insert(token.clone(), id);
+
+        token
+    }
+
+    /// Takes the ship of the player back for the session `addr`.
+    fn resume(&mut self, token: &str, addr: &Recipient<Message>) -> Result<usize, RoomError> {
+        let id = *self.resume_tokens.get(token).ok_or(RoomError::Expired)?;
+        if self.world.ship(id).is_none() {
+            return Err(RoomError::Expired);
+        }
+
+        // The old session may not have noticed it dropped yet, and is just
+        // replaced then
+        self.dropped.remove(&id);
+        self.release_keys(id);
+        self.forget_inputs(id);
+
+        log::info!("Room {}: player {id} resumed", self.id);
+        addr.do_send(Message::Resume {
+            token: self.issue_resume_token(id),
+            grace: RESUME_GRACE.as_millis() as u32,
+        });
+
+        Ok(id)
+    }
+
+    /// Forgets how to resume the player whose ship lost its last life. A
+    /// player who dropped out is not coming back for it.
+    fn eliminated(&mut self, id: usize) {
+        self.resume_tokens.retain(|_, player| *player != id);
+        if self.dropped.contains_key(&id) {
+            self.remove_player(id);
+        }
+    }
+
+    /// Lets go of every key of the player, in the replay too.
+    fn release_keys(&mut self, id: usize) {
+        self.keystates.insert(id, KeyState::new());
+        self.recorder.input(self.world.clock().tick, id, KeyState::new());
+    }
+
+    /// Drops the keys and round-trip time a session of the player sent.
+    fn forget_inputs(&mut self, id: usize) {
+        self.agents.remove(&id);
+        self.input_ticks.remove(&id);
+        self.pending_inputs.remove(&id);
+        self.input_seqs.remove(&id);
+        if self.latencies.remove(&id).is_some() {
+            self.send_pings();
+        }
+    }
+
+    fn remove_player(&mut self, id: usize) {
+        self.player_count = self.player_count.saturating_sub(1);
+
+        self.world.remove_player(id);
+        self.keystates.remove(&id);
+        self.recorder.leave(self.world.clock().tick, id);
+        self.listeners.remove(&id);
+        self.resume_tokens.retain(|_, player| *player != id);
+        self.dropped.remove(&id);
+        self.forget_inputs(id);
+    }
+
+    /// Sends the world state of this tick to external bots.
+    fn send_states(&self) {
+        if self.agents.is_empty() {
+            return;
+        }
+
+        let state = WorldState::new(&self.world);
+        for id in &self.agents {
+            if let Some(session) = self.listeners.get(id) {
+                session.do_send(Message::State {
+                    you: self.world.ship(*id).map(|ship| ship.player_num),
+                    ack_tick: self.input_ticks.get(id).copied(),
+                    data: state.clone(),
+                });
+            }
+        }
+    }
+
+    fn finish(&mut self, ranking: &[u8]) {
+        let data = finish_objects(ranking, &self.world.bounds());
+        for session in self.listeners.values() {
+            session.do_send(Message::Finish { data: data.clone() });
+        }
+
+        log::info!("Finished room {}", self.id);
+
+        // Writes on the blocking pool, the arbiter runs other rooms too
+        let replay = self.recorder.finish(self.world.clock().tick, ranking);
+        let room_id = self.id;
+        actix::spawn(async move {
+            let saved = actix_web::web::block(move || replay::save(room_id, &replay)).await;
+            if let Err(e) = saved.map_err(std::io::Error::other).and_then(|saved| saved) {
+                log::error!("Failed to save the replay of room {room_id}: {e}");
+            }
+        });
+    }
+}
+
+/// Objects every listener of the world sees.
+pub fn world_objects(world: &World) -> Vec<GameObject> {
+    let mut objects = Vec::new();
+    for (_, ship) in world.ships() {
+        // Display 'Ready' to all players
+        if !world.is_playing() && ship.is_ready {
+            objects.push(GameObject::Text { data: Text::ready(ship) });
+        }
+
+        // Anti-flicker
+        if !ship.is_game_over() {
+            objects.push(GameObject::Ship { data: ship.clone() });
+        }
+    }
+
+    for bullet in world.bullets() {
+        objects.push(GameObject::Bullet { data: bullet.clone() });
+    }
+
+    objects
+}
+
+/// The result screen for `ranking`, winner first.
+pub fn finish_objects(ranking: &[u8], bounds: &Bounds) -> Vec<GameObject> {
+    let ranking_texts: Vec<_> = ranking.iter().enumerate()
+        .map(|(i, player_num)| {
+            GameObject::Text { data: Text::ranking(i, *player_num, ranking.len(), bounds) }
+        })
+        .collect();
+
+    let game_over = vec![
+        GameObject::Text {
+            data: Text::new(
+                player_color(0),
+                Position::new(bounds.center().x, 100.0),
+                "GAME OVER".to_string(),
+            )
+        }
+    ];
+
+    [ranking_texts, game_over].concat()
+}
+
+impl Actor for GameRoom {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.last_update = Instant::now();
+
+        let interval = self.timing.tick_interval().min(self.timing.broadcast_interval());
+        ctx.run_interval(interval, |act, _ctx| act.update());
+    }
+}
+
+impl Handler<Join> for GameRoom {
+    type Result = Result<usize, RoomError>;
+
+    fn handle(&mut self, msg: Join, _ctx: &mut Self::Context) -> Self::Result {
+        let Join { id, addr, watch, bot, resume } = msg;
+
+        let id = match resume {
+            Some(token) if !watch => self.resume(&token, &addr)?,
+            _ if watch => {
+                self.spectator_count += 1;
+                id
+            },
+            _ => {
+                self.player_count += 1;
+
+                if self.world.add_player(id).is_some() {
+                    self.keystates.insert(id, KeyState::new());
+                    if let Some(ship) = self.world.ship(id) {
+                        self.recorder.join(self.world.clock().tick, id, ship);
+                    }
+                    addr.do_send(Message::Resume {
+                        token: self.issue_resume_token(id),
+                        grace: RESUME_GRACE.as_millis() as u32,
+                    });
+                }
+                id
+            },
+        };
+
+        addr.do_send(Message::Settings { data: *self.world.settings() });
+        self.listeners.insert(id, addr);
+        if bot {
+            self.agents.insert(id);
+        }
+
+        Ok(id)
+    }
+}
+
+impl Handler<Leave> for GameRoom {
+    type Result = ();
+
+    fn handle(&mut self, msg: Leave, _ctx: &mut Self::Context) {
+        let Leave { id, addr, watch } = msg;
+
+        // Left already, or resumed with another session
+        if self.listeners.get(&id) != Some(&addr) {
+            return;
+        }
+
+        if watch {
+            self.spectator_count = self.spectator_count.saturating_sub(1);
+            self.listeners.remove(&id);
+            return;
+        }
+
+        // Ships in a match wait for their player to come back
+        let in_match = self.world.is_playing() && !self.world.is_finished()
+            && self.world.ship(id).is_some_and(|ship| !ship.is_game_over());
+        if !in_match {
+            self.remove_player(id);
+            return;
+        }
+
+        log::info!("Room {}: player {id} dropped, waiting {RESUME_GRACE:?}", self.id);
+        self.listeners.remove(&id);
+        self.release_keys(id);
+        self.forget_inputs(id);
+        self.dropped.insert(id, Dropped {
+            deadline: Instant::now() + RESUME_GRACE,
+            bot: Bot::new(Difficulty::Normal, rand::random()),
+        });
+    }
+}
+
+impl Handler<Latency> for GameRoom {
+    type Result = ();
+
+    fn handle(&mut self, msg: Latency, _ctx: &mut Self::Context) {
+        // Spectators have no ping to show
+        if self.world.ship(msg.id).is_some() {
+            self.latencies.insert(msg.id, msg.rtt);
+            self.send_pings();
+        }
+    }
+}
+
+impl Handler<KeyUpdate> for GameRoom {
+    type Result = ();
+
+    fn handle(&mut self, msg: KeyUpdate, _ctx: &mut Self::Context) {
+        let KeyUpdate { id, addr, state, tick, seq } = msg;
+        // Players who dropped out have no session
+        if !self.keystates.contains_key(&id) || self.listeners.get(&id) != Some(&addr) {
+            return;
+        }
+
+        // Untagged keys apply at once, the latest wins. They were pressed
+        // about one way of the round trip ago.
+        if tick.is_none() && seq.is_none() {
+            let now = self.world.clock().tick;
+            self.keystates.insert(id, state);
+            self.recorder.input(now, id, state);
+
+            let ticks = self.one_way_ticks(id).min(u8::MAX as u64);
+            if self.world.settings().max_rewind > 0 && state.space && ticks > 0 {
+                self.world.rewind(id, ticks);
+                self.recorder.rewind(now, id, ticks as u8);
+            }
+            return;
+        }
+
+        // Keys for an older tick than the last ones arrived late
+        if let Some(tick) = tick {
+            match self.input_ticks.get(&id) {
+                Some(last) if *last > tick => return,
+                _ => self.input_ticks.insert(id, tick),
+            };
+        }
+
+        let clock = self.world.clock();
+        let max_tick = clock.tick + clock.ticks(MAX_INPUT_LEAD);
+        // Keys without a tick were meant for when they were sent
+        let tick = tick.unwrap_or_else(|| (clock.tick + 1).saturating_sub(self.one_way_ticks(id)));
+        let tick = tick.min(max_tick);
+        let input = Input { tick, seq, state };
+
+        // The newest keys are kept, they may be letting go of some
+        let pending = self.pending_inputs.entry(id).or_default();
+        if pending.len() >= MAX_PENDING_INPUTS {
+            log::warn!("Room {}: dropped keys of player {id}", self.id);
+            pending.pop_front();
+        }
+        // Keys for the same tick apply in the order they came
+        let index = pending.partition_point(|queued| queued.tick <= input.tick);
+        pending.insert(index, input);
+    }
+}
+
+impl Handler<AddBot> for GameRoom {
+    type Result = Result<BotInfo, RoomError>;
+
+    fn handle(&mut self, msg: AddBot, _ctx: &mut Self::Context) -> Self::Result {
+        if self.world.is_playing() {
+            return Err(RoomError::Playing);
+        }
+
+        // Random like session ids, so it never clashes with a player in
+        // practice
+        let id = rand::rng().random_range(0..MAX_PLAYER_ID);
+        let player_num = self.world.add_player(id).ok_or(RoomError::Full)?;
+        self.keystates.insert(id, KeyState::new());
+        if let Some(ship) = self.world.ship(id) {
+            self.recorder.join(self.world.clock().tick, id, ship);
+        }
+        self.bots.insert(id, Bot::new(msg.difficulty, rand::random()));
+        self.player_count += 1;
+
+        log::info!("Added a {:?} bot to room {}", msg.difficulty, self.id);
+
+        Ok(BotInfo { id, player_num, difficulty: msg.difficulty })
+    }
+}
+
+impl Handler<RemoveBot> for GameRoom {
+    type Result = Result<(), RoomError>;
+
+    fn handle(&mut self, msg: RemoveBot, _ctx: &mut Self::Context) -> Self::Result {
+        let id = msg.id;
+        if !self.bots.contains_key(&id) {
+            return Err(RoomError::NotFound);
+        }
+        // Bots count toward the ranking, so they stay until the match ends
+        if self.world.is_playing() {
+            return Err(RoomError::Playing);
+        }
+        self.bots.remove(&id);
+
+        self.player_count = self.player_count.saturating_sub(1);
+        self.world.remove_player(id);
+        self.keystates.remove(&id);
+        self.recorder.leave(self.world.clock().tick, id);
+
+        Ok(())
+    }
+}
+
+impl Handler<GetInfo> for GameRoom {
+    type Result = MessageResult<GetInfo>;
+
+    fn handle(&mut self, _msg: GetInfo, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.info())
+    }
+}
+
+impl Handler<Close> for GameRoom {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Close, ctx: &mut Self::Context) {
+        ctx.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game::Playback;
+
+    use super::*;
+
+    /// Takes the messages of a session and does nothing with them.
+    struct Sink;
+
+    impl Actor for Sink {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<Message> for Sink {
+        type Result = ();
+
+        fn handle(&mut self, _msg: Message, _ctx: &mut Self::Context) {}
+    }
+
+    fn session() -> Recipient<Message> {
+        Context::<Sink>::new().address().recipient()
+    }
+
+    fn room(settings: Settings) -> GameRoom {
+        GameRoom::new(0, Timing { tick_rate: 60, broadcast_rate: 30 }, settings)
+    }
+
+    /// Runs the room for one tick, however long the test took.
+    fn tick(room: &mut GameRoom) {
+        room.last_update = Instant::now();
+        room.lag = room.timing.tick_interval();
+        room.update();
+    }
+
+    fn join(room: &mut GameRoom, id: usize, addr: &Recipient<Message>, resume: Option<String>) -> Result<usize, RoomError> {
+        let msg = Join { id, addr: addr.clone(), watch: false, bot: false, resume };
+        room.handle(msg, &mut Context::new())
+    }
+
+    fn key_update(room: &mut GameRoom, id: usize, addr: &Recipient<Message>, state: KeyState, tick: Option<u64>, seq: Option<u64>) {
+        room.handle(KeyUpdate { id, addr: addr.clone(), state, tick, seq }, &mut Context::new());
+    }
+
+    fn leave(room: &mut GameRoom, id: usize, addr: &Recipient<Message>) {
+        room.handle(Leave { id, addr: addr.clone(), watch: false }, &mut Context::new());
+    }
+
+    fn token(room: &GameRoom, id: usize) -> String {
+        room.resume_tokens.iter().find(|(_, player)| **player == id).unwrap().0.clone()
+    }
+
+    /// A match of players 1 and 2 and a bot, which runs for long without
+    /// anyone being eliminated.
+    fn playing_room() -> (GameRoom, Recipient<Message>) {
+        let mut room = room(Settings { lives: 9, ..Default::default() });
+        let addr = session();
+        for id in [1, 2] {
+            join(&mut room, id, &addr, None).unwrap();
+            let state = KeyState { space: true, ..KeyState::new() };
+            key_update(&mut room, id, &addr, state, None, None);
+        }
+        add_bot(&mut room).unwrap();
+
+        while !room.world.is_playing() {
+            tick(&mut room);
+        }
+        (room, addr)
+    }
+
+    /// The replay recorded so far plays out the match as it went.
+    fn assert_recorded(room: &GameRoom) {
+        let mut playback = Playback::new(room.recorder.finish(room.world.clock().tick, &[]));
+        while playback.step().is_some() {}
+
+        assert_eq!(playback.world().clock(), room.world.clock());
+        assert!(playback.world().ships().eq(room.world.ships()));
+        assert_eq!(playback.world().bullets(), room.world.bullets());
+    }
+
+    fn add_bot(room: &mut GameRoom) -> Result<BotInfo, RoomError> {
+        room.handle(AddBot { difficulty: Difficulty::Easy }, &mut Context::new())
+    }
+
+    fn remove_bot(room: &mut GameRoom, id: usize) -> Result<(), RoomError> {
+        room.handle(RemoveBot { id }, &mut Context::new())
+    }
+
+    #[test]
+    fn remove_bot_test() {
+        let mut room = room(Settings { lives: 9, ..Default::default() });
+        let a = add_bot(&mut room).unwrap();
+        let b = add_bot(&mut room).unwrap();
+        let c = add_bot(&mut room).unwrap();
+
+        assert_eq!(remove_bot(&mut room, c.id), Ok(()));
+        assert_eq!(remove_bot(&mut room, c.id), Err(RoomError::NotFound));
+
+        while !room.world.is_playing() {
+            tick(&mut room);
+        }
+
+        // Bots count toward the ranking like players
+        assert_eq!(remove_bot(&mut room, a.id), Err(RoomError::Playing));
+        assert!(room.world.ship(a.id).is_some() && room.world.ship(b.id).is_some());
+        assert_eq!(room.info().players, 2);
+    }
+
+    #[test]
+    fn pending_inputs_overflow_test() {
+        let mut room = room(Settings::default());
+        let addr = session();
+        join(&mut room, 1, &addr, None).unwrap();
+
+        // Holds up for more keys than are kept, then lets go
+        let due = room.world.clock().tick + 10;
+        let up = KeyState { up: true, ..KeyState::new() };
+        for seq in 0..MAX_PENDING_INPUTS as u64 {
+            key_update(&mut room, 1, &addr, up, Some(due), Some(seq));
+        }
+        let seq = Some(MAX_PENDING_INPUTS as u64);
+        key_update(&mut room, 1, &addr, KeyState::new(), Some(due), seq);
+
+        for _ in 0..10 {
+            tick(&mut room);
+        }
+        assert_eq!(room.keystates[&1], KeyState::new());
+        assert_eq!(room.input_seqs[&1], MAX_PENDING_INPUTS as u64);
+    }
+
+    #[test]
+    fn resume_test() {
+        let (mut room, addr) = playing_room();
+        let player_num = room.world.ship(1).unwrap().player_num;
+        let old_token = token(&room, 1);
+
+        // A bot flies the ship while its player is away
+        leave(&mut room, 1, &addr);
+        assert!(room.dropped.contains_key(&1));
+        for _ in 0..60 {
+            tick(&mut room);
+        }
+
+        let old_addr = addr;
+        let addr = session();
+        assert_eq!(join(&mut room, 3, &addr, Some(old_token.clone())), Ok(1));
+        assert_eq!(room.world.ship(1).map(|ship| ship.player_num), Some(player_num));
+        assert!(room.dropped.is_empty() && !room.listeners.contains_key(&3));
+        assert_eq!(room.info().players, 3);
+        // Tokens are good for one resume
+        assert_ne!(token(&room, 1), old_token);
+        assert_eq!(join(&mut room, 4, &session(), Some(old_token)), Err(RoomError::Expired));
+
+        // Only the new session steers the ship
+        let up = KeyState { up: true, ..KeyState::new() };
+        key_update(&mut room, 1, &old_addr, up, None, None);
+        assert_eq!(room.keystates[&1], KeyState::new());
+        key_update(&mut room, 1, &addr, up, None, None);
+        assert_eq!(room.keystates[&1], up);
+
+        for _ in 0..60 {
+            tick(&mut room);
+        }
+        assert_recorded(&room);
+    }
+
+    #[test]
+    fn resume_expired_test() {
+        let (mut room, addr) = playing_room();
+        let expired = token(&room, 1);
+
+        leave(&mut room, 1, &addr);
+        for _ in 0..30 {
+            tick(&mut room);
+        }
+        room.dropped.get_mut(&1).unwrap().deadline = Instant::now();
+        tick(&mut room);
+
+        assert!(room.world.ship(1).is_none() && room.dropped.is_empty());
+        assert_eq!(room.info().players, 2);
+        assert_eq!(join(&mut room, 3, &session(), Some(expired)), Err(RoomError::Expired));
+        assert_recorded(&room);
+
+        // Nor for a ship which is gone
+        let gone = token(&room, 2);
+        leave(&mut room, 2, &addr);
+        room.world.remove_player(2);
+        assert_eq!(join(&mut room, 4, &session(), Some(gone.clone())), Err(RoomError::Expired));
+
+        // Once it is eliminated, the token goes and no bot flies for it
+        room.eliminated(2);
+        assert!(room.dropped.is_empty() && room.resume_tokens.is_empty());
+        assert_eq!(room.info().players, 1);
+        assert_eq!(join(&mut room, 4, &session(), Some(gone)), Err(RoomError::Expired));
+    }
+}
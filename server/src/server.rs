@@ -1,70 +1,108 @@
-use std::{collections::{HashMap, HashSet, VecDeque}, sync::{Arc, Mutex}, time::{Duration, Instant}};
-use std::thread;
-use std::f32::consts::PI;
+use std::{collections::HashMap, fmt, num::NonZeroUsize, thread, time::Duration};
 
 use actix::prelude::*;
-use rand::{Rng, rngs::ThreadRng};
+use actix_web::{http::StatusCode, ResponseError};
+use rand::{distr::Alphanumeric, Rng, rngs::ThreadRng};
+use game::{bullet, player_color, ship, Difficulty, KeyState, Settings, WorldState};
 use serde::Serialize;
-use vector::Vector;
-
-use crate::{bullet::{self, Bullet}, keystate::KeyState, ship::{self, Ship}};
-
-const COLOR_LIST: [&str; 4] = [
-    "#00ff00", // Green
-    "#ff0000", // Red
-    "#0080ff", // Blue
-    "#ffff00", // Yellow
-];
-const ORDINAL_NUMBER: [&str; 4] = [
-    "1st",
-    "2nd",
-    "3rd",
-    "4th",
-];
+use subtle::ConstantTimeEq;
+use vector::{Bounds, Position};
+
+use crate::{
+    delta::{Entity, ObjectKey},
+    room::{self, BotInfo, GameRoom, RoomInfo, RoomState, Timing},
+};
+
+const MAX_ROOMS: usize = 10000;
+// Ids of players and bots are random below this, the integers JavaScript
+// can represent, as they reach clients through the rooms API
+pub const MAX_PLAYER_ID: usize = 1 << 53;
+// Alphanumeric, about 119 bits
+const INVITE_CODE_LEN: usize = 20;
+const MAX_PASSWORD_LEN: usize = 64;
+
+// The ranking is split into columns of this many lines
+const RANKING_COLUMN_LEN: usize = 8;
+// Parts of the height of the field the ranking starts at and spans
+const RANKING_TOP: f32 = 1.0 / 3.0;
+const RANKING_HEIGHT: f32 = 0.6;
+
+/// "1st", "2nd", ... for a rank starting from 1.
+pub fn ordinal(rank: usize) -> String {
+    let suffix = match (rank % 10, rank % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+
+    format!("{rank}{suffix}")
+}
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct Text {
-    color: String,
-    pos: Vector,
-    text: String,
+    pub color: String,
+    pub pos: Position,
+    pub text: String,
 }
 
 impl Text {
-    pub fn new(color: String, pos: Vector, text: String) -> Self {
+    pub fn new(color: String, pos: Position, text: String) -> Self {
         Self { color, pos, text }
     }
 
     fn with_color_num(num: u8, x: f32, y: f32, text: String) -> Self {
         Self {
-            color: COLOR_LIST[num as usize].to_string(),
-            pos: Vector::new(x, y),
+            color: player_color(num),
+            pos: Position::new(x, y),
             text,
         }
     }
 
-    pub fn player_num(num: u8) -> Self {
-        Self::with_color_num(num, 300.0, 100.0,format!("You are Player {}", num + 1).to_string())
+    pub fn player_num(num: u8, bounds: &Bounds) -> Self {
+        Self::with_color_num(num, bounds.center().x, 100.0,format!("You are Player {}", num + 1).to_string())
     }
 
-    pub fn you(num: u8) -> Self {
-        Self::with_color_num(num, 150.0 + 100.0 * num as f32, 250.0, "YOU".to_string())
+    pub fn you(ship: &ship::Ship) -> Self {
+        let pos = ship.pos();
+        Self::new(
+            ship.color().to_string(),
+            Position::new(pos.x, pos.y - 50.0),
+            "YOU".to_string()
+        )
     }
 
-    pub fn space_to_ready(num: u8) -> Self {
-        Self::with_color_num(num, 300.0, 500.0, "Space to Ready".to_string())
+    pub fn space_to_ready(num: u8, bounds: &Bounds) -> Self {
+        Self::with_color_num(num, bounds.center().x, bounds.height - 100.0, "Space to Ready".to_string())
     }
 
-    pub fn ranking(ord: usize, num: u8) -> Self {
+    pub fn ready(ship: &ship::Ship) -> Self {
+        let pos = ship.pos();
+        Self::new(
+            ship.color().to_string(),
+            Position::new(pos.x, pos.y + 40.0),
+            "READY".to_string()
+        )
+    }
+
+    /// The line of the `ord`th (from 0) of `len` players in the ranking.
+    pub fn ranking(ord: usize, num: u8, len: usize, bounds: &Bounds) -> Self {
+        let columns = len.div_ceil(RANKING_COLUMN_LEN).max(1);
+        let rows = len.div_ceil(columns);
+        let line_height = (bounds.height * RANKING_HEIGHT / rows as f32).min(50.0);
+
+        let (column, row) = (ord / rows, ord % rows);
         Self::with_color_num(
             num,
-            300.0,
-            200.0 + 50.0 * ord as f32,
-            format!("{} Player{}", ORDINAL_NUMBER[ord], num + 1).to_string(),
+            bounds.width * (column as f32 + 0.5) / columns as f32,
+            bounds.height * RANKING_TOP + line_height * row as f32,
+            format!("{} Player{}", ordinal(ord + 1), num + 1).to_string(),
         )
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 #[serde(tag = "type")]
 pub enum GameObject {
     #[serde(rename = "ship")]
@@ -88,13 +126,95 @@ pub enum Message {
     #[serde(rename = "objects")]
     Objects {
         data: Vec<GameObject>,
+        tick: u64,
+        // The last keys of the client applied, for client prediction
+        #[serde(rename = "inputSeq", skip_serializing_if = "Option::is_none")]
+        input_seq: Option<u64>,
+    },
+    // Sent instead of objects to clients using deltas, until they ack one
+    #[serde(rename = "snapshot")]
+    Snapshot {
+        seq: u64,
+        tick: u64,
+        #[serde(rename = "inputSeq", skip_serializing_if = "Option::is_none")]
+        input_seq: Option<u64>,
+        objects: Vec<Entity>,
+        texts: Vec<GameObject>,
     },
+    // The changes since the snapshot `base`, texts are always sent whole
+    #[serde(rename = "delta")]
+    Delta {
+        seq: u64,
+        base: u64,
+        tick: u64,
+        #[serde(rename = "inputSeq", skip_serializing_if = "Option::is_none")]
+        input_seq: Option<u64>,
+        updated: Vec<Entity>,
+        removed: Vec<ObjectKey>,
+        texts: Vec<GameObject>,
+    },
+    // Stamped with the server time in ms since the Unix epoch, which the
+    // pong echoes
     #[serde(rename = "ping")]
-    Ping,
+    Ping {
+        time: u64,
+    },
+    // Round-trip time of the client and how far its clock is ahead of the
+    // server's, in ms
+    #[serde(rename = "latency")]
+    Latency {
+        rtt: u32,
+        offset: Option<i64>,
+    },
+    // Round-trip times of the players in the room
+    #[serde(rename = "pings")]
+    Pings {
+        data: Vec<PlayerPing>,
+    },
     #[serde(rename = "finish")]
     Finish {
         data: Vec<GameObject>,
     },
+    #[serde(rename = "settings")]
+    Settings {
+        data: Settings,
+    },
+    // Sent to external bots every tick instead of objects
+    #[serde(rename = "state")]
+    State {
+        // Player number of the bot, if it has a ship
+        you: Option<u8>,
+        // The newest tick the room got keys for from the bot
+        #[serde(rename = "ackTick")]
+        ack_tick: Option<u64>,
+        data: WorldState,
+    },
+    // Sent to players with a ship, who may join again with the token to
+    // take the ship back within `grace` ms of dropping out of the match
+    #[serde(rename = "resume")]
+    Resume {
+        token: String,
+        grace: u32,
+    },
+    // Colors of binary frames, sent first to clients which use them
+    #[serde(rename = "palette")]
+    Palette {
+        data: Vec<String>,
+    },
+    // The room refused the client, which is disconnected afterwards
+    #[serde(rename = "error")]
+    Error {
+        data: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerPing {
+    pub player_num: u8,
+    pub color: String,
+    // ms
+    pub rtt: u32,
 }
 
 #[derive(Message)]
@@ -106,311 +226,223 @@ pub struct Connect;
 pub struct Disconnect {
     pub id: usize,
     pub room: usize,
+    pub addr: Recipient<Message>,
     pub watch: bool,
 }
 
 #[derive(Message)]
-#[rtype(result = "usize")]
-pub struct CreateRoom;
+#[rtype(result = "()")]
+pub struct Latency {
+    pub id: usize,
+    pub room: usize,
+    pub rtt: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoomError {
+    BadRequest(String),
+    NotFound,
+    WrongPassword,
+    // The room is in a match
+    Playing,
+    Full,
+    // Every room id is taken
+    NoVacancy,
+    // The resume token is unknown or its ship is gone
+    Expired,
+}
+
+impl fmt::Display for RoomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoomError::BadRequest(e) => write!(f, "{e}"),
+            RoomError::NotFound => write!(f, "Room not found"),
+            RoomError::WrongPassword => write!(f, "Wrong password"),
+            RoomError::Playing => write!(f, "Room is playing"),
+            RoomError::Full => write!(f, "Room is full"),
+            RoomError::NoVacancy => write!(f, "No room is available"),
+            RoomError::Expired => write!(f, "Session expired"),
+        }
+    }
+}
+
+impl ResponseError for RoomError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            RoomError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            RoomError::NotFound => StatusCode::NOT_FOUND,
+            RoomError::WrongPassword => StatusCode::FORBIDDEN,
+            RoomError::Playing | RoomError::Full | RoomError::NoVacancy => StatusCode::CONFLICT,
+            RoomError::Expired => StatusCode::GONE,
+        }
+    }
+}
+
+/// Credentials a client came to a room with.
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    pub code: Option<String>,
+    pub password: Option<String>,
+    // Resume token of a player coming back to their ship
+    pub resume: Option<String>,
+}
+
+/// Who may see and enter a room.
+#[derive(Debug, Clone, Default)]
+struct Access {
+    // Invite code of a private room, which is hidden from the room list
+    code: Option<String>,
+    password: Option<String>,
+}
+
+impl Access {
+    /// Private rooms pretend not to exist without their code.
+    fn check_code(&self, credentials: &Credentials) -> Result<(), RoomError> {
+        match &self.code {
+            Some(code) if !secret_matches(credentials.code.as_deref(), code) => Err(RoomError::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    fn check(&self, credentials: &Credentials) -> Result<(), RoomError> {
+        self.check_code(credentials)?;
+        match &self.password {
+            Some(password) if !secret_matches(credentials.password.as_deref(), password) => {
+                Err(RoomError::WrongPassword)
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn annotate(&self, info: RoomInfo) -> RoomInfo {
+        RoomInfo {
+            private: self.code.is_some(),
+            locked: self.password.is_some(),
+            ..info
+        }
+    }
+}
+
+/// Compares a secret from the network in constant time, so how long it
+/// takes tells nothing about how much of it was right.
+pub fn secret_matches(given: Option<&str>, secret: &str) -> bool {
+    given.is_some_and(|given| given.as_bytes().ct_eq(secret.as_bytes()).into())
+}
+
+#[derive(Debug)]
+struct RoomEntry {
+    addr: Addr<GameRoom>,
+    access: Access,
+}
 
 #[derive(Message)]
-#[rtype(result = "()")]
+#[rtype(result = "Result<RoomInfo, RoomError>")]
+pub struct CreateRoom {
+    pub settings: Settings,
+    pub private: bool,
+    pub password: Option<String>,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), RoomError>")]
 pub struct DeleteRoom {
     pub room_id: usize,
+    pub credentials: Credentials,
 }
 
+/// Looks up a room. Private rooms need their code, but not the password.
 #[derive(Message)]
-#[rtype(result = "usize")]
-pub struct GetPlayerCount {
-    pub room_id: usize
+#[rtype(result = "Result<RoomInfo, RoomError>")]
+pub struct GetRoom {
+    pub room_id: usize,
+    pub credentials: Credentials,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<BotInfo, RoomError>")]
+pub struct AddBot {
+    pub room_id: usize,
+    pub credentials: Credentials,
+    pub difficulty: Difficulty,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), RoomError>")]
+pub struct RemoveBot {
+    pub room_id: usize,
+    pub credentials: Credentials,
+    pub bot_id: usize,
 }
 
 pub struct ListRooms;
 
 impl actix::Message for ListRooms {
-    type Result = Vec<usize>;
+    type Result = Vec<RoomInfo>;
 }
 
+/// Joins a room, as the player id given back.
 #[derive(Message)]
-#[rtype(result = "bool")]
+#[rtype(result = "Result<usize, RoomError>")]
 pub struct Join {
     pub id: usize,
     pub room: usize,
     pub addr: Recipient<Message>,
     pub watch: bool,
+    pub bot: bool,
+    pub credentials: Credentials,
 }
 
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct KeyUpdate {
     pub id: usize,
+    pub room: usize,
+    pub addr: Recipient<Message>,
     pub state: KeyState,
+    pub tick: Option<u64>,
+    pub seq: Option<u64>,
 }
 
-#[derive(Message)]
-#[rtype(result = "bool")]
-pub struct IsPlaying {
-    pub room_id: usize,
-}
-
-#[derive(Debug, Clone)]
-pub struct GameData {
-    // (RoomID, [PlayerID])
-    pub listeners: Arc<Mutex<HashMap<usize, HashSet<usize>>>>,
-    // (RoomID, [(PlayerID, Ship)])
-    pub ships: Arc<Mutex<HashMap<usize, HashMap<usize, Ship>>>>,
-    // (RoomID, [Bullet])
-    pub bullets: Arc<Mutex<HashMap<usize, Vec<Bullet>>>>,
-    // (PlayerID, PlayerSession)
-    pub sessions: Arc<Mutex<HashMap<usize, Recipient<Message>>>>,
-    // (PlayerID, KeyState)
-    pub keystates: Arc<Mutex<HashMap<usize, KeyState>>>,
-    // (RoomID, isPlaying)
-    pub is_playing: Arc<Mutex<HashMap<usize, bool>>>,
-    // (RoomID, [PlayerNum])
-    pub ranking: Arc<Mutex<HashMap<usize, Vec<u8>>>>,
-}
-
-#[derive(Debug)]
-pub struct GameThread {
-    data: GameData,
-}
-
-impl GameThread {
-    pub fn new() -> Self {
-        let listeners = Arc::new(Mutex::new(HashMap::<usize, HashSet<usize>>::new()));
-        let ships = Arc::new(Mutex::new(HashMap::<usize, HashMap<usize, Ship>>::new()));
-        let bullets = Arc::new(Mutex::new(HashMap::<usize, Vec<Bullet>>::new()));
-        let sessions = Arc::new(Mutex::new(HashMap::<usize, Recipient<Message>>::new()));
-        let keystates = Arc::new(Mutex::new(HashMap::new()));
-        let is_playing = Arc::new(Mutex::new(HashMap::new()));
-        let ranking = Arc::new(Mutex::new(HashMap::<usize, Vec<u8>>::new()));
-
-        let listeners_cloned = listeners.clone();
-        let ships_cloned = ships.clone();
-        let bullets_cloned = bullets.clone();
-        let sessions_cloned = sessions.clone();
-        let keystates_cloned = keystates.clone();
-        let is_playing_cloned = is_playing.clone();
-        let ranking_cloned = ranking.clone();
-
-        let mut all_ready_time: HashMap<usize, Instant> = HashMap::new();
-
-        thread::spawn(move || {
-            let mut before_frame = Instant::now();
-
-            loop {
-                let now = Instant::now();
-                // 60fps
-                if now.duration_since(before_frame) < Duration::from_micros(16_666) {
-                    continue;
-                }
-
-                before_frame = Instant::now();
-
-                // Game Logic
-                let mut objects = Vec::new();
-                let mut ended_games = Vec::new();
-                for (room_id, ships) in ships_cloned.lock().unwrap().iter_mut() {
-                    objects.clear();
-
-                    let mut all_ready = ships.len() > 1;
-                    let mut not_broadcast_texts = HashMap::new();
-                    for (player_id, ship) in ships.iter_mut() {
-                        all_ready &= ship.is_ready;
-
-                        if let Some(true) = is_playing_cloned.lock().unwrap().get(room_id) {
-                            // Moves ships
-                            ship.process_one_frame(
-                                keystates_cloned.lock().unwrap().get(player_id).expect("KeyState not found")
-                            );
-
-                            // Generates a bullet from the ship
-                            let mut bullets = bullets_cloned.lock().unwrap();
-
-                            if keystates_cloned.lock().unwrap().get(player_id).expect("KeyState not found").space {
-                                if let Some(bullet) = ship.fire() {
-                                    log::debug!("{bullet:?}");
-                                    bullets.get_mut(room_id).expect("Bullets not found").push(bullet);
-                                }
-                            }
-                        } else {
-                            // Display 'Ready' to all players
-                            if ship.is_ready {
-                                objects.push(GameObject::Text { data: ship.ready_text() });
-                            }
-
-                            // Display texts to one player
-                            not_broadcast_texts.insert(*player_id, vec![
-                                GameObject::Text { data: Text::player_num(ship.player_num) },
-                                GameObject::Text { data: Text::you(ship.player_num) },
-                                GameObject::Text { data: Text::space_to_ready(ship.player_num) }
-                            ]);
-
-                            // Makes a ship ready
-                            if keystates_cloned.lock().unwrap().get(player_id).expect("KeyState not found").space {
-                                ship.is_ready = true;
-                            }
-                        }
-
-                        // Anti-flicker
-                        if !ship.is_game_over() {
-                            objects.push(GameObject::Ship { data: ship.clone() });
-                        }
-                    }
-
-                    // If all ships are ready and the game is not being played
-                    // all_ready_timing = now
-                    if all_ready && !*is_playing_cloned.lock().unwrap().get(room_id).expect("isPlaying not found") {
-                        if let None = all_ready_time.get(room_id) {
-                            all_ready_time.insert(*room_id, Instant::now());
-                        }
-                    }
-
-                    // ... after 1 second, starts the game
-                    if let Some(all_ready_timing) = all_ready_time.get(room_id) {
-                        if Instant::now().duration_since(*all_ready_timing) > Duration::from_secs(1) {
-                            is_playing_cloned.lock().unwrap().insert(*room_id, true);
-                            all_ready_time.remove(room_id);
-
-                            for ship in ships.values_mut() {
-                                ship.put_on_random_place();
-                            }
-                        }
-                    }
-
-                    // Moves bullets and removes dead bullets
-                    let mut bullets = bullets_cloned.lock().unwrap();
-                    let mut bullets_alive: Vec<Bullet> = bullets.get(room_id)
-                        .expect("Bullets not found")
-                        .iter()
-                        .cloned()
-                        .filter(|b| b.is_alive())
-                        .collect();
-                    
-                    for bullet in bullets_alive.iter_mut() {
-                        bullet.move_by_one_frame();
-                        objects.push(GameObject::Bullet { data: bullet.clone() });
-                    }
-
-                    let mut dead_players_id = Vec::new();
-                    for (player_id, ship) in ships.iter_mut() {
-                        for bullet in &bullets_alive {
-                            ship.collision_process(bullet);
-                        }
-
-                        if ship.is_game_over() {
-                            dead_players_id.push((*player_id, ship.player_num));
-                        }
-                    }
-
-                    // Updates data of bullets
-                    let new_bullets = bullets.get_mut(room_id).expect("Bullets not found");
-                    *new_bullets = bullets_alive;
-
-                    // Send data of objects to clients
-                    let listeners = listeners_cloned.lock().unwrap();
-                    let listeners = listeners.get(room_id).expect("Listeners not found");
-                    for player_id in listeners {
-                        if let Some(session) = sessions_cloned.lock().unwrap().get(player_id) {
-                            let mut data = objects.clone();
-                            if let Some(not_broadcast_texts) = not_broadcast_texts.remove(player_id) {
-                                data.extend(not_broadcast_texts);
-                            }
-
-                            session.do_send(Message::Objects { data });
-                        }
-                    }
-
-                    // Delete dead ships
-                    for (id, num) in dead_players_id {
-                        ships.remove(&id);
-                        ranking_cloned.lock().unwrap().get_mut(room_id).expect("Ranking not found")
-                            .push(num);
-                    }
-
-                    // If the number of players is 1, game is over
-                    if ships.len() == 1 && *is_playing_cloned.lock().unwrap().get(room_id).expect("isPlaying not found") {
-                        ranking_cloned.lock().unwrap().get_mut(room_id).expect("Ranking not found")
-                            .push(ships.values().next().unwrap().player_num);
-
-                        let mut ranking = ranking_cloned.lock().unwrap().get_mut(room_id)
-                            .expect("Ranking not found")
-                            .clone();
-
-
-                        ranking.reverse();
-                        for player_id in listeners {
-                            if let Some(session) = sessions_cloned.lock().unwrap().get(player_id) {
-                                let ranking_texts: Vec<_> = ranking.iter().enumerate()
-                                    .map(|(i, player_num)| {
-                                        GameObject::Text { data: Text::ranking(i, *player_num) }
-                                    })
-                                    .collect();
-
-                                let game_over = vec![
-                                    GameObject::Text {
-                                        data: Text::new(
-                                            COLOR_LIST[0].to_string(),
-                                            Vector::new(300.0, 100.0),
-                                            "GAME OVER".to_string(),
-                                        )
-                                    }
-                                ];
-
-                                let data = [ranking_texts, game_over].concat();
-                                session.do_send(Message::Finish { data });
-                            }
-                        }
-
-                        ended_games.push(*room_id);
-                    }
-                }
-
-                while let Some(room_id) = ended_games.pop() {
-                    ships_cloned.lock().unwrap().remove(&room_id);
-                }
-            }
-        });
-
-        Self {
-            data: GameData {
-                listeners,
-                ships,
-                bullets,
-                sessions,
-                keystates,
-                is_playing,
-                ranking,
-            }
-        }
-    }
-
-    pub fn get_game_data(&self) -> GameData {
-        self.data.clone()
-    }
-}
-
+/// Routes messages from sessions to the room actors.
+///
+/// Rooms are spread over a pool of arbiters, one per available core, so
+/// rooms tick in parallel.
 #[derive(Debug)]
 pub struct GameServer {
-    room_num: HashMap<usize, usize>,
+    rooms: HashMap<usize, RoomEntry>,
     rng: ThreadRng,
-    thread: GameThread,
-    player_num_pool: HashMap<usize, VecDeque<u8>>,
+    timing: Timing,
+    arbiters: Vec<Arbiter>,
+    next_arbiter: usize,
 }
 
 impl GameServer {
-    pub fn new() -> Self {
+    pub fn new(timing: Timing) -> Self {
+        let arbiter_num = thread::available_parallelism()
+            .map(NonZeroUsize::get)
+            .unwrap_or(1);
+
         Self {
-            room_num: HashMap::new(),
+            rooms: HashMap::new(),
             rng: rand::rng(),
-            thread: GameThread::new(),
-            player_num_pool: HashMap::new(),
+            timing,
+            arbiters: (0..arbiter_num).map(|_| Arbiter::new()).collect(),
+            next_arbiter: 0,
         }
     }
 
-    pub fn get_game_data(&self) -> GameData {
-        self.thread.get_game_data()
+    /// The room if the client may enter it.
+    fn room_addr(&self, room_id: usize, credentials: &Credentials) -> Result<Addr<GameRoom>, RoomError> {
+        let entry = self.rooms.get(&room_id).ok_or(RoomError::NotFound)?;
+        entry.access.check(credentials)?;
+        Ok(entry.addr.clone())
+    }
+
+    fn spawn_room(&mut self, room: GameRoom) -> Addr<GameRoom> {
+        let arbiter = &self.arbiters[self.next_arbiter];
+        self.next_arbiter = (self.next_arbiter + 1) % self.arbiters.len();
+
+        GameRoom::start_in_arbiter(&arbiter.handle(), move |_| room)
     }
 }
 
@@ -422,7 +454,7 @@ impl Handler<Connect> for GameServer {
     type Result = usize;
 
     fn handle(&mut self, _msg: Connect, _ctx: &mut Self::Context) -> Self::Result {
-        self.rng.random_range(0..=usize::MAX)
+        self.rng.random_range(0..MAX_PLAYER_ID)
     }
 }
 
@@ -430,150 +462,166 @@ impl Handler<Disconnect> for GameServer {
     type Result = ();
 
     fn handle(&mut self, msg: Disconnect, _ctx: &mut Self::Context) {
-        let Disconnect { id, room, watch } = msg;
+        let Disconnect { id, room, addr, watch } = msg;
 
-        match self.room_num.get_mut(&room) {
-            Some(0) => (),
-            Some(room_num) => {
-                if *room_num > 0 && !watch {
-                    *room_num -= 1;
-                }
-            },
-            None => return,
-        }
-
-        let game_data = self.get_game_data();
-        let mut ships = game_data.ships.lock().unwrap();
-        let mut sessions = game_data.sessions.lock().unwrap();
-        let mut keystates = game_data.keystates.lock().unwrap();
-        let mut listeners = game_data.listeners.lock().unwrap();
-
-        if let Some(ships_in_room) = ships.get_mut(&room) {
-            if let Some(player_ship) = ships_in_room.get(&id) {
-                self.player_num_pool.get_mut(&room)
-                    .expect("Room not found")
-                    .push_back(player_ship.player_num);
-            }
-            ships_in_room.remove(&id);
+        if let Some(entry) = self.rooms.get(&room) {
+            entry.addr.do_send(room::Leave { id, addr, watch });
         }
-        keystates.remove(&id);
-        sessions.remove(&id);
-        listeners.get_mut(&room).unwrap().remove(&id);
     }
 }
 
 impl Handler<CreateRoom> for GameServer {
-    type Result = usize;
+    type Result = Result<RoomInfo, RoomError>;
+
+    fn handle(&mut self, msg: CreateRoom, _ctx: &mut Self::Context) -> Self::Result {
+        let CreateRoom { settings, private, password } = msg;
 
-    fn handle(&mut self, _msg: CreateRoom, _ctx: &mut Self::Context) -> Self::Result {
-        let mut id = self.rng.random_range(0..10000);
-        while let Some(_) = self.room_num.get(&id) {
-            id = self.rng.random_range(0..10000);
+        settings.validate()
+            .map_err(|e| RoomError::BadRequest(format!("Invalid settings: {e}")))?;
+        if password.as_ref().is_some_and(|p| p.is_empty() || p.chars().count() > MAX_PASSWORD_LEN) {
+            return Err(RoomError::BadRequest(format!(
+                "Password must be 1 to {MAX_PASSWORD_LEN} characters"
+            )));
         }
 
-        self.room_num.insert(id, 0);
+        if self.rooms.len() >= MAX_ROOMS {
+            return Err(RoomError::NoVacancy);
+        }
 
-        let game_data = self.get_game_data();
-        let mut listeners = game_data.listeners.lock().unwrap();
-        let mut ships = game_data.ships.lock().unwrap();
-        let mut bullets = game_data.bullets.lock().unwrap();
-        let mut is_playing = game_data.is_playing.lock().unwrap();
-        let mut ranking = game_data.ranking.lock().unwrap();
+        let mut id = self.rng.random_range(0..MAX_ROOMS);
+        while self.rooms.contains_key(&id) {
+            id = self.rng.random_range(0..MAX_ROOMS);
+        }
 
-        listeners.insert(id, HashSet::new());
-        ships.insert(id, HashMap::new());
-        bullets.insert(id, Vec::new());
-        is_playing.insert(id, false);
-        ranking.insert(id, Vec::new());
-        self.player_num_pool.insert(id, VecDeque::from([0, 1, 2, 3]));
+        let code = private.then(|| {
+            (&mut self.rng).sample_iter(Alphanumeric)
+                .take(INVITE_CODE_LEN)
+                .map(char::from)
+                .collect()
+        });
+        let access = Access { code: code.clone(), password };
+
+        let room = GameRoom::new(id, self.timing, settings);
+        // Only the creator gets to know the invite code
+        let info = RoomInfo { code, ..access.annotate(room.info()) };
+        let addr = self.spawn_room(room);
+        self.rooms.insert(id, RoomEntry { addr, access });
 
-        log::info!("Created room {id}");
+        log::info!("Created {} room {id}", if private { "private" } else { "public" });
 
-        id
+        Ok(info)
     }
 }
 
 impl Handler<DeleteRoom> for GameServer {
-    type Result = ();
+    type Result = ResponseActFuture<Self, Result<(), RoomError>>;
 
-    fn handle(&mut self, msg: DeleteRoom, _ctx: &mut Self::Context) {
-        self.room_num.remove(&msg.room_id);
-        self.player_num_pool.remove(&msg.room_id);
-
-        let game_data = self.get_game_data();
-        let mut listeners = game_data.listeners.lock().unwrap();
-        let mut ships = game_data.ships.lock().unwrap();
-        let mut bullets = game_data.bullets.lock().unwrap();
-        let mut is_playing = game_data.is_playing.lock().unwrap();
-        let mut ranking = game_data.ranking.lock().unwrap();
-
-        listeners.remove(&msg.room_id);
-        ships.remove(&msg.room_id);
-        bullets.remove(&msg.room_id);
-        is_playing.remove(&msg.room_id);
-        if let Some(_) = ranking.remove(&msg.room_id) {
-            log::info!("Deleted room {}", msg.room_id);
-        }
+    fn handle(&mut self, msg: DeleteRoom, _ctx: &mut Self::Context) -> Self::Result {
+        let room_id = msg.room_id;
+        let room = self.room_addr(room_id, &msg.credentials);
+
+        Box::pin(
+            async move {
+                match room?.send(room::GetInfo).await {
+                    Ok(info) if info.state == RoomState::Playing => Err(RoomError::Playing),
+                    _ => Ok(()),
+                }
+            }
+            .into_actor(self)
+            .map(move |res, act, _ctx| {
+                res?;
+
+                // The room may have been deleted while waiting for it
+                let entry = act.rooms.remove(&room_id).ok_or(RoomError::NotFound)?;
+                entry.addr.do_send(room::Close);
+                log::info!("Deleted room {room_id}");
+
+                Ok(())
+            })
+        )
+    }
+}
+
+impl Handler<GetRoom> for GameServer {
+    type Result = ResponseFuture<Result<RoomInfo, RoomError>>;
+
+    fn handle(&mut self, msg: GetRoom, _ctx: &mut Self::Context) -> Self::Result {
+        let room = self.rooms.get(&msg.room_id)
+            .ok_or(RoomError::NotFound)
+            .and_then(|entry| {
+                entry.access.check_code(&msg.credentials)?;
+                Ok((entry.addr.clone(), entry.access.clone()))
+            });
+
+        Box::pin(async move {
+            let (addr, access) = room?;
+            let info = addr.send(room::GetInfo).await.map_err(|_| RoomError::NotFound)?;
+            Ok(access.annotate(info))
+        })
+    }
+}
+
+impl Handler<AddBot> for GameServer {
+    type Result = ResponseFuture<Result<BotInfo, RoomError>>;
+
+    fn handle(&mut self, msg: AddBot, _ctx: &mut Self::Context) -> Self::Result {
+        let room = self.room_addr(msg.room_id, &msg.credentials);
+        Box::pin(async move {
+            room?.send(room::AddBot { difficulty: msg.difficulty }).await
+                .map_err(|_| RoomError::NotFound)?
+        })
+    }
+}
+
+impl Handler<RemoveBot> for GameServer {
+    type Result = ResponseFuture<Result<(), RoomError>>;
+
+    fn handle(&mut self, msg: RemoveBot, _ctx: &mut Self::Context) -> Self::Result {
+        let room = self.room_addr(msg.room_id, &msg.credentials);
+        Box::pin(async move {
+            room?.send(room::RemoveBot { id: msg.bot_id }).await
+                .map_err(|_| RoomError::NotFound)?
+        })
     }
 }
 
 impl Handler<ListRooms> for GameServer {
-    type Result = MessageResult<ListRooms>;
+    type Result = ResponseFuture<Vec<RoomInfo>>;
 
     fn handle(&mut self, _msg: ListRooms, _ctx: &mut Self::Context) -> Self::Result {
-        let mut room_list = Vec::new();
-
-        for (room_id, room_num) in &self.room_num {
-            if *room_num > 0 {
-                room_list.push(*room_id);
+        let rooms: Vec<_> = self.rooms.values()
+            .filter(|entry| entry.access.code.is_none())
+            .map(|entry| (entry.addr.clone(), entry.access.clone()))
+            .collect();
+
+        Box::pin(async move {
+            let mut room_list = Vec::new();
+
+            for (addr, access) in rooms {
+                // Rooms stopping in the meantime are left out
+                if let Ok(info) = addr.send(room::GetInfo).await {
+                    room_list.push(access.annotate(info));
+                }
             }
-        }
 
-        MessageResult(room_list)
+            room_list.sort_by_key(|room| room.id);
+            room_list
+        })
     }
 }
 
 impl Handler<Join> for GameServer {
-    type Result = bool;
-
-    fn handle(&mut self, msg: Join, _ctx: &mut Self::Context) -> bool {
-        let Join { id, room, addr, watch } = msg;
-
-        let game_data = self.get_game_data();
-        let mut listeners = game_data.listeners.lock().unwrap();
-        let mut ships = game_data.ships.lock().unwrap();
-        let mut sessions = game_data.sessions.lock().unwrap();
-        let mut keystates = game_data.keystates.lock().unwrap();
-        let is_playing = game_data.is_playing.lock().unwrap();
-
-        if !watch {
-            if let Some(room_num) = self.room_num.get_mut(&room) {
-                *room_num += 1;
-            } else {
-                return false;
-            }
+    type Result = ResponseFuture<Result<usize, RoomError>>;
 
-            if let Some(false) = is_playing.get(&room) {
-                let player_num = self.player_num_pool.get_mut(&room)
-                    .expect("Room not found")
-                    .pop_front()
-                    .unwrap();
-
-                keystates.insert(id, KeyState::new());
-                let ships_in_room = ships.get_mut(&room).unwrap();
-                ships_in_room.insert(id, Ship::new(
-                    player_num,
-                    COLOR_LIST[player_num as usize].to_string(),
-                    vector::Vector { x: 100.0 * player_num as f32 + 150.0, y: 300.0 },
-                    -PI / 2.0,
-                ));
-            }
-        }
-        sessions.insert(id, addr);
-        let listeners_in_room = listeners.get_mut(&room).unwrap();
-        listeners_in_room.insert(id);
+    fn handle(&mut self, msg: Join, _ctx: &mut Self::Context) -> Self::Result {
+        let Join { id, room, addr, watch, bot, credentials } = msg;
 
-        true
+        let room = self.room_addr(room, &credentials);
+        let resume = credentials.resume;
+        Box::pin(async move {
+            room?.send(room::Join { id, addr, watch, bot, resume }).await
+                .map_err(|_| RoomError::NotFound)?
+        })
     }
 }
 
@@ -581,27 +629,57 @@ impl Handler<KeyUpdate> for GameServer {
     type Result = ();
 
     fn handle(&mut self, msg: KeyUpdate, _ctx: &mut Self::Context) -> Self::Result {
-        let KeyUpdate { id, state } = msg;
+        let KeyUpdate { id, room, addr, state, tick, seq } = msg;
 
-        self.get_game_data().keystates.lock().unwrap()
-            .insert(id, state);
+        if let Some(entry) = self.rooms.get(&room) {
+            entry.addr.do_send(room::KeyUpdate { id, addr, state, tick, seq });
+        }
     }
 }
 
-impl Handler<GetPlayerCount> for GameServer {
-    type Result = usize;
+impl Handler<Latency> for GameServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Latency, _ctx: &mut Self::Context) -> Self::Result {
+        let Latency { id, room, rtt } = msg;
 
-    fn handle(&mut self, msg: GetPlayerCount, _ctx: &mut Self::Context) -> Self::Result {
-        *self.room_num.get(&msg.room_id).unwrap_or(&0)
+        if let Some(entry) = self.rooms.get(&room) {
+            entry.addr.do_send(room::Latency { id, rtt });
+        }
     }
 }
 
-impl Handler<IsPlaying> for GameServer {
-    type Result = bool;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ordinal_test() {
+        let ordinals: Vec<_> = [1, 2, 3, 4, 11, 12, 13, 21, 22, 101, 111].into_iter()
+            .map(ordinal)
+            .collect();
+
+        assert_eq!(ordinals, [
+            "1st", "2nd", "3rd", "4th", "11th", "12th", "13th", "21st", "22nd", "101st", "111th",
+        ]);
+    }
 
-    fn handle(&mut self, msg: IsPlaying, _ctx: &mut Self::Context) -> Self::Result {
-        *self.get_game_data().is_playing.lock().unwrap()
-            .get(&msg.room_id)
-            .unwrap_or(&false)
+    #[test]
+    fn access_test() {
+        let credentials = |code: Option<&str>, password: Option<&str>| Credentials {
+            code: code.map(str::to_string),
+            password: password.map(str::to_string),
+            resume: None,
+        };
+        let access = Access { code: Some("code".to_string()), password: Some("pass".to_string()) };
+
+        assert_eq!(access.check(&credentials(Some("code"), Some("pass"))), Ok(()));
+        assert_eq!(access.check_code(&credentials(Some("code"), None)), Ok(()));
+        assert_eq!(access.check(&credentials(Some("code"), Some("x"))), Err(RoomError::WrongPassword));
+        assert_eq!(access.check(&credentials(None, Some("pass"))), Err(RoomError::NotFound));
+        assert_eq!(Access::default().check(&credentials(None, None)), Ok(()));
+
+        assert!(secret_matches(Some("pass"), "pass"));
+        assert!(!secret_matches(Some("pas"), "pass") && !secret_matches(None, "pass"));
     }
 }
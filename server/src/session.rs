@@ -1,14 +1,28 @@
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use actix::prelude::*;
 use actix_web_actors::ws;
 use serde::Deserialize;
 
-use crate::{keystate::KeyState, server};
+use game::KeyState;
+
+use crate::{binary::{self, Format}, delta::Snapshots, server};
 
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(15);
 
+/// The server time pings are stamped with, in ms since the Unix epoch.
+pub fn unix_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// `duration` in whole ms, as sent to clients.
+pub fn saturating_millis(duration: Duration) -> u32 {
+    u32::try_from(duration.as_millis()).unwrap_or(u32::MAX)
+}
+
 #[derive(Debug)]
 pub struct GameSession {
     pub id: usize,
@@ -16,19 +30,52 @@ pub struct GameSession {
     pub room: usize,
     pub addr: Addr<server::GameServer>,
     pub watch: bool,
+    // An external bot authenticated by a token
+    pub bot: bool,
+    pub format: Format,
+    // Set if the client asked for deltas
+    pub snapshots: Option<Snapshots>,
+    // Smoothed over the pongs
+    pub rtt: Option<Duration>,
+    // The stamp of the last ping, until its pong comes back
+    pub ping_time: Option<u64>,
+    // The code comes with the request, the password with the join message
+    pub credentials: server::Credentials,
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type")]
 enum ClientMessage {
     #[serde(rename = "join")]
-    Join,
+    Join {
+        #[serde(default)]
+        password: Option<String>,
+        // The token of the last session, to take its ship back
+        #[serde(default)]
+        resume: Option<String>,
+    },
     #[serde(rename = "keystate")]
     KeyState {
         data: KeyState,
+        #[serde(default)]
+        tick: Option<u64>,
+        #[serde(default)]
+        seq: Option<u64>,
+    },
+    // The client got the snapshot `seq`
+    #[serde(rename = "ack")]
+    Ack {
+        seq: u64,
     },
     #[serde(rename = "pong")]
-    Pong,
+    Pong {
+        // The time of the ping
+        #[serde(default)]
+        time: Option<u64>,
+        // The time of the client when it got the ping, in ms since the Unix epoch
+        #[serde(default, rename = "clientTime")]
+        client_time: Option<u64>,
+    },
     #[serde(rename = "finish")]
     Finish,
     Error(String),
@@ -41,14 +88,45 @@ impl GameSession {
                 act.addr.do_send(server::Disconnect {
                     id: act.id,
                     room: act.room,
+                    addr: ctx.address().recipient(),
                     watch: act.watch,
                 });
                 ctx.stop();
                 return;
             }
 
-            ctx.text(serde_json::to_string(&server::Message::Ping).unwrap());
+            let time = unix_millis();
+            act.ping_time = Some(time);
+            let msg = server::Message::Ping { time };
+            ctx.text(serde_json::to_string(&msg).unwrap());
+        });
+    }
+
+    /// Updates the round-trip time with a pong for the ping sent at `time`,
+    /// and tells the client and its room. Pongs for any other time than the
+    /// last ping, or for a ping answered already, are ignored.
+    fn pong(&mut self, time: u64, client_time: Option<u64>, ctx: &mut ws::WebsocketContext<Self>) {
+        if self.ping_time != Some(time) {
+            return;
+        }
+        self.ping_time = None;
+
+        let sample = Duration::from_millis(unix_millis().saturating_sub(time));
+        let rtt = match self.rtt {
+            Some(rtt) => (rtt * 7 + sample) / 8,
+            None => sample,
+        };
+        self.rtt = Some(rtt);
+
+        // The client got the ping about half the round trip after it was sent
+        let offset = client_time.map(|client_time| {
+            i64::try_from(client_time).unwrap_or(i64::MAX)
+                .saturating_sub((time + sample.as_millis() as u64 / 2) as i64)
         });
+        let msg = server::Message::Latency { rtt: saturating_millis(rtt), offset };
+        ctx.text(serde_json::to_string(&msg).unwrap());
+
+        self.addr.do_send(server::Latency { id: self.id, room: self.room, rtt });
     }
 }
 
@@ -58,6 +136,11 @@ impl Actor for GameSession {
     fn started(&mut self, ctx: &mut Self::Context) {
         self.hb(ctx);
 
+        if self.format == Format::Binary {
+            let msg = server::Message::Palette { data: binary::palette() };
+            ctx.text(serde_json::to_string(&msg).unwrap());
+        }
+
         self.addr
             .send(server::Connect)
             .into_actor(self)
@@ -74,10 +157,11 @@ impl Actor for GameSession {
             .wait(ctx);
     }
 
-    fn stopping(&mut self, _ctx: &mut Self::Context) -> Running {
+    fn stopping(&mut self, ctx: &mut Self::Context) -> Running {
         self.addr.do_send(server::Disconnect {
             id: self.id,
             room: self.room,
+            addr: ctx.address().recipient(),
             watch: self.watch,
         });
         Running::Stop
@@ -88,6 +172,20 @@ impl Handler<server::Message> for GameSession {
     type Result = ();
 
     fn handle(&mut self, msg: server::Message, ctx: &mut Self::Context) {
+        let msg = match (msg, &mut self.snapshots) {
+            (server::Message::Objects { data, tick, input_seq }, Some(snapshots)) => {
+                snapshots.encode(data, tick, input_seq)
+            },
+            (msg, _) => msg,
+        };
+
+        if self.format == Format::Binary {
+            if let Some(frame) = binary::encode(&msg) {
+                ctx.binary(frame);
+                return;
+            }
+        }
+
         ctx.text(serde_json::to_string(&msg).unwrap());
     }
 }
@@ -119,46 +217,70 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for GameSession {
 
                 log::debug!("{msg:?}");
                 match msg {
-                    ClientMessage::Join => {
+                    ClientMessage::Join { password, resume } => {
+                        self.credentials.password = password;
+                        self.credentials.resume = resume;
                         self.addr
                             .send(server::Join {
                                 id: self.id,
                                 room: self.room,
                                 addr: ctx.address().recipient(),
                                 watch: self.watch,
+                                bot: self.bot,
+                                credentials: self.credentials.clone(),
                             })
                             .into_actor(self)
-                            .then(|res, _act, ctx| {
+                            .then(|res, act, ctx| {
                                 match res {
-                                    Ok(true) => (),
-                                    _ => ctx.stop(),
+                                    // Players resuming play as their old id
+                                    Ok(Ok(id)) => act.id = id,
+                                    Ok(Err(e)) => {
+                                        let msg = server::Message::Error { data: e.to_string() };
+                                        ctx.text(serde_json::to_string(&msg).unwrap());
+                                        ctx.close(None);
+                                        ctx.stop();
+                                    },
+                                    Err(_) => ctx.stop(),
                                 }
                                 fut::ready(())
                             })
                             .wait(ctx);
                     },
-                    ClientMessage::KeyState { data } => {
+                    ClientMessage::KeyState { data, tick, seq } => {
                         self.addr
                             .send(server::KeyUpdate {
                                 id: self.id,
+                                room: self.room,
+                                addr: ctx.address().recipient(),
                                 state: data,
+                                tick,
+                                seq,
                             })
                             .into_actor(self)
                             .then(|res, _act, ctx| {
-                                if let Err(_) = res {
+                                if res.is_err() {
                                     ctx.stop();
                                 }
                                 fut::ready(())
                             })
                             .wait(ctx);
                     },
-                    ClientMessage::Pong => {
+                    ClientMessage::Ack { seq } => {
+                        if let Some(snapshots) = &mut self.snapshots {
+                            snapshots.ack(seq);
+                        }
+                    },
+                    ClientMessage::Pong { time, client_time } => {
                         self.hb = Instant::now();
+                        if let Some(time) = time {
+                            self.pong(time, client_time, ctx);
+                        }
                     },
                     ClientMessage::Finish => {
                         self.addr
                             .send(server::DeleteRoom {
-                                room_id: self.room
+                                room_id: self.room,
+                                credentials: self.credentials.clone(),
                             })
                             .into_actor(self)
                             .then(|_res, _act, _ctx| fut::ready(()))
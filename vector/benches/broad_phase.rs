@@ -0,0 +1,103 @@
+//! Collision checks of a tick with and without the grid.
+//!
+//! Run with `cargo bench -p vector`. Every ship is swept against every bullet
+//! which may hit it, as `World::step` does, on fields of the smallest and
+//! largest sizes.
+
+use std::{hint::black_box, time::Instant};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use vector::{Bounds, Grid, Position, Vec2, MARGIN};
+
+const SHIPS: usize = 12;
+const BULLETS: [usize; 4] = [50, 200, 500, 1000];
+const TICKS: usize = 2000;
+const HIT_AREA_RADIUS: f32 = 12.0;
+const CELL_SIZE: f32 = 60.0;
+
+// (Position before the tick, motion in the tick)
+type Body = (Position, Vec2);
+
+fn bodies(rng: &mut impl Rng, bounds: &Bounds, count: usize, max_speed: f32) -> Vec<Body> {
+    (0..count)
+        .map(|_| {
+            let pos = Position::new(
+                rng.random_range(-MARGIN..bounds.width + MARGIN),
+                rng.random_range(-MARGIN..bounds.height + MARGIN),
+            );
+            let speed = Vec2::from_angle(rng.random_range(0.0..std::f32::consts::TAU)) * max_speed;
+            (pos, speed)
+        })
+        .collect()
+}
+
+fn all_pairs(bounds: &Bounds, ships: &[Body], bullets: &[Body]) -> usize {
+    let mut hits = 0;
+    for (from, motion) in ships {
+        for (pos, speed) in bullets {
+            if bounds.sweep(*pos, *speed - *motion, *from, HIT_AREA_RADIUS).is_some() {
+                hits += 1;
+            }
+        }
+    }
+    hits
+}
+
+fn with_grid(grid: &mut Grid, nearby: &mut Vec<usize>, ships: &[Body], bullets: &[Body]) -> usize {
+    let bounds = grid.bounds();
+    grid.clear();
+    for (i, (pos, speed)) in bullets.iter().enumerate() {
+        grid.insert(i, *pos, *speed, 0.0);
+    }
+
+    let mut hits = 0;
+    for (from, motion) in ships {
+        grid.query(*from, *motion, HIT_AREA_RADIUS, nearby);
+        for (pos, speed) in nearby.iter().map(|&i| &bullets[i]) {
+            if bounds.sweep(*pos, *speed - *motion, *from, HIT_AREA_RADIUS).is_some() {
+                hits += 1;
+            }
+        }
+    }
+    hits
+}
+
+/// Mean time of `f` over `TICKS` runs, in µs.
+fn measure(mut f: impl FnMut() -> usize) -> (f64, usize) {
+    let hits = f();
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        black_box(f());
+    }
+    (start.elapsed().as_secs_f64() * 1e6 / TICKS as f64, hits)
+}
+
+fn main() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+    println!("{:>9} {:>5} {:>7} {:>12} {:>12}", "field", "ships", "bullets", "all pairs", "grid");
+    for bounds in [Bounds::new(480.0, 480.0), Bounds::new(960.0, 960.0)] {
+        let mut grid = Grid::new(bounds, CELL_SIZE);
+        let mut nearby = Vec::new();
+
+        for count in BULLETS {
+            let ships = bodies(&mut rng, &bounds, SHIPS, 2.0);
+            // 450 px/s at 30 Hz, the fastest bullets
+            let bullets = bodies(&mut rng, &bounds, count, 15.0);
+
+            let (naive, expected) = measure(|| all_pairs(&bounds, &ships, &bullets));
+            let (grid_time, hits) = measure(|| with_grid(&mut grid, &mut nearby, &ships, &bullets));
+            assert_eq!(hits, expected);
+
+            println!(
+                "{:>9} {:>5} {:>7} {:>9.2} µs {:>9.2} µs",
+                format!("{}x{}", bounds.width, bounds.height),
+                SHIPS,
+                count,
+                naive,
+                grid_time,
+            );
+        }
+    }
+}
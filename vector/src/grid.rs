@@ -0,0 +1,151 @@
+use crate::{Bounds, Position, Vec2, MARGIN};
+
+/// A uniform grid over the field, wrapped around its edges like positions
+/// are, for the broad phase of collisions.
+///
+/// Items are put in every cell the box around their way through the tick
+/// touches, so two items can only meet if they share a cell. Cells are
+/// stretched a little so that a whole number of them covers the field and
+/// its margins.
+#[derive(Clone, Debug)]
+pub struct Grid {
+    bounds: Bounds,
+    cols: usize,
+    rows: usize,
+    cell_width: f32,
+    cell_height: f32,
+    // Indices of the items in each cell, row by row
+    cells: Vec<Vec<usize>>,
+}
+
+impl Grid {
+    /// A grid over `bounds` with cells of about `cell_size` px.
+    pub fn new(bounds: Bounds, cell_size: f32) -> Self {
+        let cols = (bounds.wrap_width() / cell_size).ceil().max(1.0) as usize;
+        let rows = (bounds.wrap_height() / cell_size).ceil().max(1.0) as usize;
+
+        Self {
+            bounds,
+            cols,
+            rows,
+            cell_width: bounds.wrap_width() / cols as f32,
+            cell_height: bounds.wrap_height() / rows as f32,
+            cells: vec![Vec::new(); cols * rows],
+        }
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    /// Empties every cell, keeping their memory for the next tick.
+    pub fn clear(&mut self) {
+        for cell in self.cells.iter_mut() {
+            cell.clear();
+        }
+    }
+
+    /// Adds item `index`, a circle of `radius` moving from `from` by `motion`.
+    pub fn insert(&mut self, index: usize, from: Position, motion: Vec2, radius: f32) {
+        for cell in self.cells_of(from, motion, radius) {
+            self.cells[cell].push(index);
+        }
+    }
+
+    /// Fills `found` with the items that may meet a circle of `radius`
+    /// moving from `from` by `motion`, in ascending order and each once.
+    pub fn query(&self, from: Position, motion: Vec2, radius: f32, found: &mut Vec<usize>) {
+        found.clear();
+        for cell in self.cells_of(from, motion, radius) {
+            found.extend_from_slice(&self.cells[cell]);
+        }
+        found.sort_unstable();
+        found.dedup();
+    }
+
+    /// Cells touched by the box around the way, each once.
+    fn cells_of(&self, from: Position, motion: Vec2, radius: f32) -> impl Iterator<Item = usize> {
+        let to = from + motion;
+        let cols = span(from.x.min(to.x) - radius, from.x.max(to.x) + radius, self.cell_width, self.cols);
+        let rows = span(from.y.min(to.y) - radius, from.y.max(to.y) + radius, self.cell_height, self.rows);
+
+        let width = self.cols;
+        rows.flat_map(move |row| cols.clone().map(move |col| row * width + col))
+    }
+}
+
+/// Indices of the cells of `size` from `min` to `max`, wrapped into `count`.
+fn span(min: f32, max: f32, size: f32, count: usize) -> impl Iterator<Item = usize> + Clone {
+    let first = ((min + MARGIN) / size).floor() as i64;
+    let last = ((max + MARGIN) / size).floor() as i64;
+    let len = (last - first + 1).min(count as i64);
+
+    (first..first + len).map(move |i| i.rem_euclid(count as i64) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    #[test]
+    fn query_test() {
+        let bounds = Bounds::default();
+        let mut grid = Grid::new(bounds, 60.0);
+        let mut found = Vec::new();
+
+        grid.insert(0, Position::new(100.0, 100.0), Vec2::ZERO, 0.0);
+        grid.insert(1, Position::new(400.0, 400.0), Vec2::new(-20.0, 0.0), 0.0);
+        // Across the seam, from the right margin to the left edge
+        grid.insert(2, Position::new(610.0, 300.0), Vec2::new(20.0, 0.0), 0.0);
+
+        grid.query(Position::new(105.0, 95.0), Vec2::ZERO, 12.0, &mut found);
+        assert_eq!(found, vec![0]);
+        grid.query(Position::new(0.0, 300.0), Vec2::ZERO, 3.0, &mut found);
+        assert_eq!(found, vec![2]);
+
+        // A way over the whole field finds everything, once
+        grid.query(Position::new(-15.0, 300.0), Vec2::new(700.0, 100.0), 200.0, &mut found);
+        assert_eq!(found, vec![0, 1, 2]);
+
+        grid.clear();
+        grid.query(Position::new(105.0, 95.0), Vec2::ZERO, 12.0, &mut found);
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn matches_all_pairs_test() {
+        // Nothing the sweep of every pair finds is missed
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        for bounds in [Bounds::default(), Bounds::new(900.0, 480.0)] {
+            let mut grid = Grid::new(bounds, 60.0);
+            let mut found = Vec::new();
+
+            for _ in 0..100 {
+                let mut random = |max: f32| {
+                    let pos = Position::new(
+                        rng.random_range(-MARGIN..bounds.width + MARGIN),
+                        rng.random_range(-MARGIN..bounds.height + MARGIN),
+                    );
+                    (pos, Vec2::new(rng.random_range(-max..=max), rng.random_range(-max..=max)))
+                };
+                let bullets: Vec<_> = (0..300).map(|_| random(15.0)).collect();
+                let (from, motion) = random(5.0);
+
+                grid.clear();
+                for (i, (pos, speed)) in bullets.iter().enumerate() {
+                    grid.insert(i, *pos, *speed, 0.0);
+                }
+                grid.query(from, motion, 12.0, &mut found);
+
+                for (i, (pos, speed)) in bullets.iter().enumerate() {
+                    if bounds.sweep(*pos, *speed - motion, from, 12.0).is_some() {
+                        assert!(found.contains(&i), "{pos:?} {speed:?} {from:?} {motion:?}");
+                    }
+                }
+            }
+        }
+    }
+}
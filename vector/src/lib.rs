@@ -1,35 +1,57 @@
-use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign};
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
 use std::default::Default;
 use serde::{Serialize, Deserialize};
 
-pub const WIDTH: i32 = 600;
-pub const HEIGHT: i32 = 600;
-const MARGIN: i32 = 15;
+mod grid;
 
+pub use grid::Grid;
+
+// Objects may go this far off the field before they wrap around
+pub const MARGIN: f32 = 15.0;
+
+/// A free 2D vector, for velocities and offsets. It never wraps.
 #[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
-pub struct Vector {
+pub struct Vec2 {
     pub x: f32,
     pub y: f32,
 }
 
-impl Vector {
-    pub fn new(mut x: f32, mut y: f32) -> Self {
-        while x > WIDTH as f32 + MARGIN as f32 {
-            x -= WIDTH as f32 + MARGIN as f32 * 2.0;
-        }
-        while x < -MARGIN as f32 {
-            x += WIDTH as f32 + MARGIN as f32 * 2.0;
-        }
-        while y > HEIGHT as f32 + MARGIN as f32 {
-            y -= HEIGHT as f32 + MARGIN as f32 * 2.0;
-        }
-        while y < -MARGIN as f32 {
-            y += WIDTH as f32 + MARGIN as f32 * 2.0;
-        }
+impl Vec2 {
+    pub const ZERO: Self = Self { x: 0.0, y: 0.0 };
 
+    pub fn new(x: f32, y: f32) -> Self {
         Self { x, y }
     }
 
+    /// The unit vector at `rad`, clockwise from the x axis as the y axis
+    /// points down.
+    pub fn from_angle(rad: f32) -> Self {
+        Self::new(rad.cos(), rad.sin())
+    }
+
+    /// The angle of the vector as `atan2`, the inverse of [`Vec2::from_angle`].
+    pub fn angle(&self) -> f32 {
+        self.y.atan2(self.x)
+    }
+
+    pub fn dot(&self, rhs: Self) -> f32 {
+        self.x * rhs.x + self.y * rhs.y
+    }
+
+    pub fn length2(&self) -> f32 {
+        self.dot(*self)
+    }
+
+    pub fn length(&self) -> f32 {
+        self.length2().sqrt()
+    }
+
+    /// The unit vector in the same direction, or zero for zero.
+    pub fn normalize(&self) -> Self {
+        let length = self.length();
+        if length > 0.0 { *self / length } else { Self::ZERO }
+    }
+
     pub fn rotate(&self, rad: f32) -> Self {
         Self::new(
             self.x * rad.cos() - self.y * rad.sin(),
@@ -37,100 +59,465 @@ impl Vector {
         )
     }
 
-    pub fn dist2(a: Vector, b: Vector) -> f32 {
-        (a.x - b.x) * (a.x - b.x) + (a.y - b.y) * (a.y - b.y)
+    /// `self` at `t = 0` to `rhs` at `t = 1`.
+    pub fn lerp(&self, rhs: Self, t: f32) -> Self {
+        *self + (rhs - *self) * t
+    }
+}
+
+/// A point on the field. Moving it by a [`Vec2`] may leave the field,
+/// [`Bounds::wrap`] puts it back.
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct Position {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Position {
+    pub fn new(x: f32, y: f32) -> Self {
+        Self { x, y }
+    }
+
+    /// Squared distance, without crossing the edges of the field.
+    pub fn dist2(a: Position, b: Position) -> f32 {
+        (a - b).length2()
+    }
+
+    /// `self` at `t = 0` to `rhs` at `t = 1`, without crossing the edges.
+    pub fn lerp(&self, rhs: Self, t: f32) -> Self {
+        *self + (rhs - *self) * t
+    }
+}
+
+/// The field of a world, `width` x `height` px with a [`MARGIN`] on every
+/// side. Positions past the margin come back in on the other side.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for Bounds {
+    fn default() -> Self {
+        Self::new(600.0, 600.0)
+    }
+}
+
+impl Bounds {
+    pub fn new(width: f32, height: f32) -> Self {
+        Self { width, height }
+    }
+
+    /// Where `pos` is on the field, wrapped around the edges.
+    pub fn wrap(&self, pos: Position) -> Position {
+        Position::new(
+            (pos.x + MARGIN).rem_euclid(self.wrap_width()) - MARGIN,
+            (pos.y + MARGIN).rem_euclid(self.wrap_height()) - MARGIN,
+        )
+    }
+
+    /// `true` if `pos` is on the field or its margin, and so needs no wrapping.
+    pub fn contains(&self, pos: Position) -> bool {
+        (-MARGIN..=self.width + MARGIN).contains(&pos.x)
+            && (-MARGIN..=self.height + MARGIN).contains(&pos.y)
+    }
+
+    pub fn center(&self) -> Position {
+        Position::new(self.width / 2.0, self.height / 2.0)
+    }
+
+    /// The shortest way from `from` to `to`, which may cross the edges of the
+    /// field. Components are within half the wrapped size.
+    pub fn displacement(&self, from: Position, to: Position) -> Vec2 {
+        Vec2::new(
+            shortest(to.x - from.x, self.wrap_width()),
+            shortest(to.y - from.y, self.wrap_height()),
+        )
+    }
+
+    /// Like [`Position::dist2`], across the edges of the field.
+    pub fn wrapped_dist2(&self, a: Position, b: Position) -> f32 {
+        self.displacement(a, b).length2()
+    }
+
+    /// The angle of the shortest way from `from` to `to`, as `atan2`.
+    pub fn angle_to(&self, from: Position, to: Position) -> f32 {
+        self.displacement(from, to).angle()
+    }
+
+    /// The earliest time in `[0, 1]` a point moving from `from` by `motion`
+    /// comes within `radius` of `center`, across the edges of the field.
+    /// Two moving circles meet when one moves by the difference of their
+    /// motions against the sum of their radii.
+    pub fn sweep(&self, from: Position, motion: Vec2, center: Position, radius: f32) -> Option<f32> {
+        sweep_circle(self.displacement(center, from), motion, radius)
+    }
+
+    // Positions wrap around after this far, the field and margins on both sides
+    fn wrap_width(&self) -> f32 {
+        self.width + MARGIN * 2.0
     }
+
+    fn wrap_height(&self) -> f32 {
+        self.height + MARGIN * 2.0
+    }
+}
+
+/// The earliest time in `[0, 1]` at which `start + motion * t` is within
+/// `radius` of the origin.
+pub fn sweep_circle(start: Vec2, motion: Vec2, radius: f32) -> Option<f32> {
+    // |start + motion t|^2 = radius^2
+    let c = start.length2() - radius * radius;
+    if c <= 0.0 {
+        return Some(0.0);
+    }
+
+    let a = motion.length2();
+    let b = start.dot(motion);
+    // Moving away or not at all
+    if a == 0.0 || b >= 0.0 {
+        return None;
+    }
+
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let t = (-b - discriminant.sqrt()) / a;
+    (t <= 1.0).then_some(t)
 }
 
-impl Add<Self> for Vector {
+/// `d` wrapped into `[-period / 2, period / 2)`.
+fn shortest(d: f32, period: f32) -> f32 {
+    (d + period / 2.0).rem_euclid(period) - period / 2.0
+}
+
+impl Add<Self> for Vec2 {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self::Output {
-        Vector::new(self.x + rhs.x, self.y + rhs.y)
+        Vec2::new(self.x + rhs.x, self.y + rhs.y)
     }
 }
 
-impl AddAssign<Self> for Vector {
+impl AddAssign<Self> for Vec2 {
     fn add_assign(&mut self, rhs: Self) {
-        *self = Vector::new(self.x + rhs.x, self.y + rhs.y);
+        *self = *self + rhs;
     }
 }
 
-impl Sub<Self> for Vector {
+impl Sub<Self> for Vec2 {
     type Output = Self;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        Vector::new(self.x - rhs.x, self.y - rhs.y)
+        Vec2::new(self.x - rhs.x, self.y - rhs.y)
     }
 }
 
-impl SubAssign<Self> for Vector {
+impl SubAssign<Self> for Vec2 {
     fn sub_assign(&mut self, rhs: Self) {
-        *self = Vector::new(self.x - rhs.x, self.y - rhs.y);
+        *self = *self - rhs;
     }
 }
 
-impl Mul<f32> for Vector {
+impl Mul<f32> for Vec2 {
     type Output = Self;
 
     fn mul(self, rhs: f32) -> Self::Output {
-        Vector::new(self.x * rhs, self.y * rhs)
+        Vec2::new(self.x * rhs, self.y * rhs)
     }
 }
 
-impl MulAssign<f32> for Vector {
+impl MulAssign<f32> for Vec2 {
     fn mul_assign(&mut self, rhs: f32) {
-        *self = Vector::new(self.x * rhs, self.y * rhs);
+        *self = *self * rhs;
     }
 }
 
-impl Div<f32> for Vector {
+impl Div<f32> for Vec2 {
     type Output = Self;
 
     fn div(self, rhs: f32) -> Self::Output {
-        Vector::new(self.x / rhs, self.y / rhs)
+        Vec2::new(self.x / rhs, self.y / rhs)
     }
 }
 
-impl DivAssign<f32> for Vector {
+impl DivAssign<f32> for Vec2 {
     fn div_assign(&mut self, rhs: f32) {
-        *self = Vector::new(self.x / rhs, self.y / rhs);
+        *self = *self / rhs;
+    }
+}
+
+impl Neg for Vec2 {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Vec2::new(-self.x, -self.y)
+    }
+}
+
+impl Add<Vec2> for Position {
+    type Output = Self;
+
+    fn add(self, rhs: Vec2) -> Self::Output {
+        Position::new(self.x + rhs.x, self.y + rhs.y)
+    }
+}
+
+impl AddAssign<Vec2> for Position {
+    fn add_assign(&mut self, rhs: Vec2) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub<Vec2> for Position {
+    type Output = Self;
+
+    fn sub(self, rhs: Vec2) -> Self::Output {
+        Position::new(self.x - rhs.x, self.y - rhs.y)
+    }
+}
+
+impl SubAssign<Vec2> for Position {
+    fn sub_assign(&mut self, rhs: Vec2) {
+        *self = *self - rhs;
+    }
+}
+
+/// The way from `rhs` to `self`, without crossing the edges of the field.
+impl Sub<Self> for Position {
+    type Output = Vec2;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Vec2::new(self.x - rhs.x, self.y - rhs.y)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::f32::consts::PI;
+
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
     use super::*;
 
+    // Cases per property, from a fixed seed so failures reproduce
+    const CASES: usize = 10_000;
+    const EPSILON: f32 = 1e-3;
+
+    /// How `Vector::new` wrapped every position before positions and
+    /// velocities were split, on the 600x600 field.
+    fn reference_wrap(mut x: f32, mut y: f32) -> (f32, f32) {
+        while x > 615.0 {
+            x -= 630.0;
+        }
+        while x < -15.0 {
+            x += 630.0;
+        }
+        while y > 615.0 {
+            y -= 630.0;
+        }
+        while y < -15.0 {
+            y += 630.0;
+        }
+
+        (x, y)
+    }
+
+    fn position(rng: &mut impl Rng, bounds: &Bounds) -> Position {
+        Position::new(
+            rng.random_range(-MARGIN..bounds.width + MARGIN),
+            rng.random_range(-MARGIN..bounds.height + MARGIN),
+        )
+    }
+
+    fn vec2(rng: &mut impl Rng, max: f32) -> Vec2 {
+        Vec2::new(rng.random_range(-max..=max), rng.random_range(-max..=max))
+    }
+
+    fn check<R: Rng + SeedableRng>(property: impl Fn(&mut R)) {
+        let mut rng = R::seed_from_u64(0);
+        for _ in 0..CASES {
+            property(&mut rng);
+        }
+    }
+
+    /// `a` and `b` are the same place, maybe on the two sides of the seam.
+    fn same_place(bounds: &Bounds, a: Position, b: Position) -> bool {
+        bounds.wrapped_dist2(a, b) < EPSILON * EPSILON
+    }
+
+    #[test]
+    fn wrap_test() {
+        let bounds = Bounds::default();
+        let a = Position::new(300.0, 400.0);
+        let b = Position::new(100.0, 1100.0);
+
+        assert_eq!(bounds.wrap(a), a);
+        assert_eq!(bounds.wrap(b), Position::new(100.0, 470.0));
+        assert_eq!(bounds.wrap(a + (b - Position::default())), Position::new(400.0, 240.0));
+        assert!(!bounds.contains(b) && bounds.contains(bounds.wrap(b)));
+
+        // Velocities are not positions
+        assert_eq!(Vec2::new(-20.0, 0.0) * 2.0, Vec2::new(-40.0, 0.0));
+
+        let wide = Bounds::new(900.0, 480.0);
+        assert_eq!(wide.wrap(Position::new(700.0, 500.0)), Position::new(700.0, -10.0));
+    }
+
     #[test]
-    fn overflow_test() {
-        let a = Vector::new(300.0, 400.0);
-        let b = Vector::new(100.0, 1100.0);
+    fn wrap_matches_reference_test() {
+        let bounds = Bounds::default();
+        check(|rng: &mut ChaCha8Rng| {
+            let x = rng.random_range(-2000.0..2000.0);
+            let y = rng.random_range(-2000.0..2000.0);
+            let (rx, ry) = reference_wrap(x, y);
 
-        assert_eq!(a, Vector::new(300.0, 400.0));
-        assert_eq!(b, Vector::new(100.0, 470.0));
-        assert_eq!(a + b, Vector::new(400.0, 240.0));
+            let wrapped = bounds.wrap(Position::new(x, y));
+            assert!(bounds.contains(wrapped));
+            assert!(same_place(&bounds, wrapped, Position::new(rx, ry)), "{x}, {y}");
+        });
+    }
+
+    #[test]
+    fn moving_matches_reference_test() {
+        // Ships and bullets moved by adding their speed to their position
+        let bounds = Bounds::default();
+        check(|rng: &mut ChaCha8Rng| {
+            let pos = position(rng, &bounds);
+            let speed = vec2(rng, 15.0);
+            let (rx, ry) = reference_wrap(pos.x + speed.x, pos.y + speed.y);
+
+            assert!(same_place(&bounds, bounds.wrap(pos + speed), Position::new(rx, ry)));
+            // Small speeds used to stay as they were
+            assert_eq!(reference_wrap(speed.x, speed.y), (speed.x, speed.y));
+        });
+    }
+
+    #[test]
+    fn displacement_test() {
+        let bounds = Bounds::new(900.0, 480.0);
+        check(|rng: &mut ChaCha8Rng| {
+            let (a, b) = (position(rng, &bounds), position(rng, &bounds));
+            let d = bounds.displacement(a, b);
+
+            assert!(d.x.abs() <= 465.0 + EPSILON && d.y.abs() <= 255.0 + EPSILON);
+            assert!(same_place(&bounds, bounds.wrap(a + d), bounds.wrap(b)));
+            assert!(d.length() <= (b - a).length() + EPSILON);
+            assert!((d.length() - bounds.displacement(b, a).length()).abs() < EPSILON);
+        });
+    }
+
+    #[test]
+    fn vec2_test() {
+        check(|rng: &mut ChaCha8Rng| {
+            let (a, b) = (vec2(rng, 100.0), vec2(rng, 100.0));
+            let rad = rng.random_range(-PI..PI);
+
+            assert_eq!(-(-a), a);
+            assert_eq!(a + -a, Vec2::ZERO);
+            assert_eq!(a.dot(b), b.dot(a));
+            assert!((a.dot(a) - a.length() * a.length()).abs() < EPSILON * a.length2().max(1.0));
+            assert!((a.rotate(rad).length() - a.length()).abs() < EPSILON);
+            assert!((Vec2::from_angle(rad).angle() - rad).abs() < EPSILON);
+            assert!((Vec2::from_angle(rad).length() - 1.0).abs() < EPSILON);
+            assert!(a.lerp(b, 0.0) == a && (a.lerp(b, 1.0) - b).length() < EPSILON);
+
+            if a.length() > EPSILON {
+                assert!((a.normalize().length() - 1.0).abs() < EPSILON);
+                assert!((a.normalize().angle() - a.angle()).abs() < EPSILON);
+            }
+        });
+
+        assert_eq!(Vec2::ZERO.normalize(), Vec2::ZERO);
     }
 
     #[test]
     fn dist_test() {
-        let mut before = Vector::new(0.0, 0.0);
-        let mut after = Vector::new(0.0, 0.0);
-        let diff = Vector::new(100.0, 0.0);
+        let bounds = Bounds::default();
+        let mut before = Position::new(0.0, 0.0);
+        let mut after = Position::new(0.0, 0.0);
+        let diff = Vec2::new(100.0, 0.0);
 
-        after += diff;
+        after = bounds.wrap(after + diff);
         for _ in 0..6 {
-            assert!(Vector::dist2(before, after) <= 10001.0);
+            assert!(Position::dist2(before, after) <= 10001.0);
             before = after;
-            after += diff;
+            after = bounds.wrap(after + diff);
         }
-        assert!(Vector::dist2(before, after) >= 10001.0);
+        assert!(Position::dist2(before, after) >= 10001.0);
+        assert!(bounds.wrapped_dist2(before, after) <= 10001.0);
+    }
+
+    #[test]
+    fn wrapped_dist_test() {
+        let bounds = Bounds::default();
+        let a = Position::new(599.0, 300.0);
+        let b = Position::new(1.0, 300.0);
+
+        // 16 px to the right edge of the margin, 16 px back in from the left
+        assert_eq!(bounds.displacement(a, b), Vec2::new(32.0, 0.0));
+        assert_eq!(bounds.displacement(b, a), Vec2::new(-32.0, 0.0));
+        assert_eq!(bounds.wrapped_dist2(a, b), 32.0 * 32.0);
+        assert_eq!(bounds.wrapped_dist2(a, Position::new(500.0, 300.0)), 99.0 * 99.0);
+
+        let c = Position::new(300.0, 610.0);
+        let d = Position::new(300.0, -10.0);
+        assert_eq!(bounds.wrapped_dist2(c, d), 10.0 * 10.0);
+        assert!((bounds.angle_to(c, d) - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+        assert!(bounds.angle_to(a, b).abs() < 1e-6);
+
+        // The seam moves with the size
+        assert_eq!(Bounds::new(900.0, 600.0).wrapped_dist2(a, b), 332.0 * 332.0);
+    }
+
+    #[test]
+    fn sweep_test() {
+        let bounds = Bounds::default();
+        let center = Position::new(300.0, 300.0);
+
+        // Passes through the middle of the circle between two ends outside it
+        let t = bounds.sweep(Position::new(280.0, 300.0), Vec2::new(40.0, 0.0), center, 12.0);
+        assert_eq!(t, Some(0.2));
+        assert_eq!(bounds.sweep(Position::new(280.0, 320.0), Vec2::new(40.0, 0.0), center, 12.0), None);
+        assert_eq!(bounds.sweep(Position::new(320.0, 300.0), Vec2::new(40.0, 0.0), center, 12.0), None);
+        assert_eq!(bounds.sweep(center, Vec2::ZERO, center, 12.0), Some(0.0));
+
+        // Across the seam, from the right margin to the left edge, 20 px away
+        let t = bounds.sweep(Position::new(610.0, 300.0), Vec2::new(20.0, 0.0), Position::new(0.0, 300.0), 3.0);
+        assert!(t.is_some_and(|t| (t - 0.85).abs() < EPSILON));
+    }
+
+    #[test]
+    fn sweep_matches_sampling_test() {
+        check(|rng: &mut ChaCha8Rng| {
+            let start = vec2(rng, 60.0);
+            let motion = vec2(rng, 60.0);
+            let radius = rng.random_range(1.0..20.0);
+
+            let hit = sweep_circle(start, motion, radius);
+            if let Some(t) = hit {
+                assert!((0.0..=1.0).contains(&t));
+                assert!((start + motion * t).length() <= radius + EPSILON);
+            }
+
+            // Nothing is missed, and nothing earlier is
+            for i in 0..=100 {
+                let s = i as f32 / 100.0;
+                if (start + motion * s).length() < radius - EPSILON {
+                    assert!(hit.is_some_and(|t| t <= s + EPSILON), "{start:?} {motion:?} {radius}");
+                }
+            }
+        });
     }
 
     #[test]
     fn serialize_test() {
-        let v = Vector::new(123.0, 456.0);
+        let v = Position::new(123.0, 456.0);
 
         assert_eq!(serde_json::to_string(&v).unwrap(), "{\"x\":123.0,\"y\":456.0}");
+        assert_eq!(serde_json::to_string(&Vec2::new(-1.0, 0.5)).unwrap(), "{\"x\":-1.0,\"y\":0.5}");
     }
 }